@@ -0,0 +1,140 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::command;
+use uuid::Uuid;
+
+// size limits shared by local and synced attachments
+pub const MAX_ATTACHMENT_BYTES: usize = 10 * 1024 * 1024;
+pub const MAX_NOTE_ATTACHMENT_BYTES: usize = 50 * 1024 * 1024;
+
+// folder (inside the notebook) attachments are stored in
+const ATTACHMENT_DIR: &str = "attachments";
+
+// mime type based on the file extension
+pub fn mime_type(name: &str) -> &'static str {
+    let extension = name.rsplit('.').next().unwrap_or_default().to_lowercase();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+// keeps file names markdown-friendly (no spaces or brackets that would need escaping in links)
+pub fn sanitize_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+
+    let sanitized = sanitized.trim_matches(|c| c == '.' || c == '-').to_string();
+    if sanitized.is_empty() { "attachment".to_string() } else { sanitized }
+}
+
+// checks a single upload and the note's running total against the limits
+pub fn check_size(size: usize, existing: usize) -> Result<(), String> {
+    if size > MAX_ATTACHMENT_BYTES {
+        return Err(format!("Attachment exceeds the {} MB limit", MAX_ATTACHMENT_BYTES / (1024 * 1024)));
+    }
+
+    if existing + size > MAX_NOTE_ATTACHMENT_BYTES {
+        return Err(format!("Note attachments exceed the {} MB limit", MAX_NOTE_ATTACHMENT_BYTES / (1024 * 1024)));
+    }
+    Ok(())
+}
+
+// folder name for a note, markdown-friendly like sanitize_name but never shared by two notes
+// anything outside [A-Za-z0-9.-] (and a leading dot) becomes "_XX" per byte, so "a b" is "a_20b" and "a-b" stays "a-b"
+fn folder_name(stem: &str) -> String {
+    let mut folder = String::new();
+    for (i, byte) in stem.bytes().enumerate() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' => folder.push(byte as char),
+            b'.' if i > 0 => folder.push('.'),
+            _ => folder.push_str(&format!("_{:02X}", byte)),
+        }
+    }
+    folder
+}
+
+// attachments folder of a note and the relative path used in markdown links
+fn note_folder(note: &Path) -> Result<(PathBuf, String), String> {
+    let notebook = note.parent().ok_or("Invalid note path")?;
+    let stem = note.file_stem().ok_or("Invalid note path")?.to_string_lossy();
+    let folder = folder_name(&stem);
+
+    Ok((notebook.join(ATTACHMENT_DIR).join(&folder), format!("{}/{}", ATTACHMENT_DIR, folder)))
+}
+
+// total size of the files already attached to a note
+fn folder_size(folder: &Path) -> usize {
+    fs::read_dir(folder)
+        .map(|dir| {
+            dir.filter_map(|entry| entry.ok()?.metadata().ok())
+                .map(|metadata| metadata.len() as usize)
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+// stores a binary file next to a local note, returns the reference to use in markdown
+#[command]
+pub fn save_attachment(note: &str, name: &str, data: Vec<u8>) -> Result<String, String> {
+    let (folder, relative) = note_folder(Path::new(note))?;
+    check_size(data.len(), folder_size(&folder))?;
+
+    // short unique prefix so re-pasting "image.png" never overwrites an earlier one
+    let id = Uuid::new_v4().simple().to_string();
+    let file_name = format!("{}-{}", &id[..8], sanitize_name(name));
+
+    fs::create_dir_all(&folder).map_err(|e| e.to_string())?;
+    fs::write(folder.join(&file_name), data).map_err(|e| e.to_string())?;
+
+    Ok(format!("{}/{}", relative, file_name))
+}
+
+// reads a local attachment by its markdown reference
+#[command]
+pub fn read_attachment(note: &str, reference: &str) -> Result<Vec<u8>, String> {
+    let (folder, relative) = note_folder(Path::new(note))?;
+    let file_name = reference
+        .strip_prefix(&format!("{}/", relative))
+        .filter(|file_name| !file_name.contains(['/', '\\']) && !file_name.starts_with('.'))
+        .ok_or("Invalid attachment reference")?;
+
+    fs::read(folder.join(file_name)).map_err(|e| e.to_string())
+}
+
+// deletes local attachments the note no longer references, returns the removed references
+#[command]
+pub fn collect_attachments(note: &str) -> Result<Vec<String>, String> {
+    let (folder, relative) = note_folder(Path::new(note))?;
+    let content = fs::read_to_string(note).map_err(|e| e.to_string())?;
+
+    let entries = match fs::read_dir(&folder) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()), // nothing attached yet
+    };
+
+    let mut removed: Vec<String> = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let reference = format!("{}/{}", relative, path.file_name().unwrap_or_default().to_string_lossy());
+
+        if path.is_file() && !content.contains(&reference) {
+            fs::remove_file(&path).map_err(|e| e.to_string())?;
+            removed.push(reference);
+        }
+    }
+
+    // drop the folder once it is empty
+    if fs::read_dir(&folder).map(|mut dir| dir.next().is_none()).unwrap_or(false) {
+        fs::remove_dir(&folder).map_err(|e| e.to_string())?;
+    }
+
+    Ok(removed)
+}
@@ -0,0 +1,312 @@
+use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+use super::client::conn;
+use super::sessions::session_user;
+use super::crypto::{DataKey, UserKeys};
+use super::error::{DbError, DbResult};
+use super::keys::vault_key;
+
+// Collaborator roles, ordered from least to most access
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+// Implementation for role methods
+impl Role {
+    // name stored in redis and sent to the frontend
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+
+    // parses a role name
+    pub fn parse(role: &str) -> DbResult<Role> {
+        match role {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "owner" => Ok(Role::Owner),
+            _ => Err(DbError::Other("Invalid role".to_string())),
+        }
+    }
+}
+
+// AUTHORIZE SESSION
+// the session must be live and belong to the user whose keys are unlocked
+pub async fn authorize(token: &str, keys: &UserKeys) -> DbResult<()> {
+    let mut connection = conn().await?;
+
+    let user_id = session_user(&mut connection, token).await?;
+    if user_id != keys.user_id {
+        return Err(DbError::Forbidden);
+    }
+
+    Ok(())
+}
+
+// GET VAULT ROLE
+pub async fn vault_role(connection: &mut MultiplexedConnection, user_id: &str, vault_id: &str) -> DbResult<Option<Role>> {
+    // get HASH
+    let role: Option<String> = connection.hget(format!("members:{}", vault_id), user_id).await?;
+    if let Some(role) = role {
+        return Role::parse(&role).map(Some);
+    }
+
+    // vaults created before sharing only record their owner
+    let owner: Option<String> = connection.hget(format!("vault:{}", vault_id), "user_id").await?;
+    Ok((owner.as_deref() == Some(user_id)).then_some(Role::Owner))
+}
+
+// checks the user holds at least `required` on the vault
+pub async fn require_role(connection: &mut MultiplexedConnection, user_id: &str, vault_id: &str, required: Role) -> DbResult<Role> {
+    match vault_role(connection, user_id, vault_id).await? {
+        Some(role) if role >= required => Ok(role),
+        _ => Err(DbError::Forbidden),
+    }
+}
+
+// OPEN VAULT (role check + vault key)
+pub async fn open_vault(connection: &mut MultiplexedConnection, keys: &UserKeys, vault_id: &str, required: Role) -> DbResult<DataKey> {
+    require_role(connection, &keys.user_id, vault_id, required).await?;
+    vault_key(connection, keys, vault_id).await
+}
+
+// OPEN NOTEBOOK (vault ID and vault key of the notebook's vault)
+pub async fn open_notebook(connection: &mut MultiplexedConnection, keys: &UserKeys, notebook_id: &str, required: Role) -> DbResult<(String, DataKey)> {
+    // get HASH
+    let vault_id: Option<String> = connection.hget(format!("notebook:{}", notebook_id), "vault_id").await?;
+    let vault_id = vault_id.ok_or(DbError::Forbidden)?;
+
+    let key = open_vault(connection, keys, &vault_id, required).await?;
+    Ok((vault_id, key))
+}
+
+// OPEN NOTE (notebook ID and vault key of the note's vault)
+pub async fn open_note(connection: &mut MultiplexedConnection, keys: &UserKeys, note_id: &str, required: Role) -> DbResult<(String, DataKey)> {
+    // get HASH
+    let notebook_id: Option<String> = connection.hget(format!("note:{}", note_id), "notebook_id").await?;
+    let notebook_id = notebook_id.ok_or(DbError::Forbidden)?;
+
+    let (_, key) = open_notebook(connection, keys, &notebook_id, required).await?;
+    Ok((notebook_id, key))
+}
+
+// OPEN SCOPE ("note:<id>", "notebook:<id>" or "vault:<id>", vault key of what it points at)
+pub async fn open_scope(connection: &mut MultiplexedConnection, keys: &UserKeys, scope: &str, required: Role) -> DbResult<DataKey> {
+    match scope.split_once(':') {
+        Some(("note", id)) => Ok(open_note(connection, keys, id, required).await?.1),
+        Some(("notebook", id)) => Ok(open_notebook(connection, keys, id, required).await?.1),
+        Some(("vault", id)) => open_vault(connection, keys, id, required).await,
+        _ => Err(DbError::Invalid("Expected a note, notebook or vault".to_string())),
+    }
+}
+
+// cross-user access, run against the redis in DATABASE_URL: cargo test -- --ignored
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::crypto::{encrypt, generate_key, generate_key_pair, seal_key};
+    use crate::db::keys::create_vault_key;
+    use crate::db::ops::{create_note, create_notebook, delete_vault, get_notes, read_note, save_note};
+    use crate::db::sessions::generate_session_token;
+    use uuid::Uuid;
+
+    fn user() -> (UserKeys, String) {
+        let (private_key, public_key) = generate_key_pair();
+        let keys = UserKeys { user_id: Uuid::new_v4().to_string(), data_key: generate_key(), private_key };
+        (keys, public_key)
+    }
+
+    // a vault with one notebook and note, owned by `owner` and shared with `viewer`
+    struct Fixture {
+        vault_id: String,
+        notebook_id: String,
+        note_id: String,
+        owner: UserKeys,
+        viewer: UserKeys,
+        stranger: UserKeys,
+    }
+
+    async fn connect() -> MultiplexedConnection {
+        conn().await.expect("these tests need the redis in DATABASE_URL")
+    }
+
+    async fn fixture(connection: &mut MultiplexedConnection) -> Fixture {
+        let (owner, _) = user();
+        let (viewer, viewer_public) = user();
+        let (stranger, _) = user();
+        let fixture = Fixture {
+            vault_id: Uuid::new_v4().to_string(),
+            notebook_id: Uuid::new_v4().to_string(),
+            note_id: Uuid::new_v4().to_string(),
+            owner,
+            viewer,
+            stranger,
+        };
+
+        let vault_key = create_vault_key(connection, &fixture.owner, &fixture.vault_id).await.unwrap();
+        let _: () = connection.hset(format!("vault:{}", fixture.vault_id), "user_id", &fixture.owner.user_id).await.unwrap();
+        let _: () = connection.hset_multiple(
+            format!("members:{}", fixture.vault_id),
+            &[(&fixture.owner.user_id, Role::Owner.as_str()), (&fixture.viewer.user_id, Role::Viewer.as_str())],
+        ).await.unwrap();
+        let _: () = connection.hset(
+            format!("vault_keys:{}", fixture.vault_id),
+            &fixture.viewer.user_id,
+            seal_key(&viewer_public, &vault_key).unwrap(),
+        ).await.unwrap();
+        let _: () = connection.hset(format!("notebook:{}", fixture.notebook_id), "vault_id", &fixture.vault_id).await.unwrap();
+        let _: () = connection.hset_multiple(
+            format!("note:{}", fixture.note_id),
+            &[("notebook_id", fixture.notebook_id.clone()), ("content", encrypt(&vault_key, "secret").unwrap())],
+        ).await.unwrap();
+
+        fixture
+    }
+
+    async fn cleanup(connection: &mut MultiplexedConnection, fixture: &Fixture) {
+        let _: () = connection.del(&[
+            format!("vault:{}", fixture.vault_id),
+            format!("members:{}", fixture.vault_id),
+            format!("vault_keys:{}", fixture.vault_id),
+            format!("notebook:{}", fixture.notebook_id),
+            format!("note:{}", fixture.note_id),
+        ]).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis"]
+    async fn non_member_is_forbidden() {
+        let mut connection = connect().await;
+        let fixture = fixture(&mut connection).await;
+        let stranger = &fixture.stranger;
+
+        let vault = open_vault(&mut connection, stranger, &fixture.vault_id, Role::Viewer).await;
+        let notebook = open_notebook(&mut connection, stranger, &fixture.notebook_id, Role::Viewer).await;
+        let note = open_note(&mut connection, stranger, &fixture.note_id, Role::Viewer).await;
+        let role = require_role(&mut connection, &stranger.user_id, &fixture.vault_id, Role::Viewer).await;
+        cleanup(&mut connection, &fixture).await;
+
+        assert!(matches!(vault, Err(DbError::Forbidden)));
+        assert!(matches!(notebook, Err(DbError::Forbidden)));
+        assert!(matches!(note, Err(DbError::Forbidden)));
+        assert!(matches!(role, Err(DbError::Forbidden)));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis"]
+    async fn viewer_cannot_edit() {
+        let mut connection = connect().await;
+        let fixture = fixture(&mut connection).await;
+        let viewer = &fixture.viewer;
+
+        let read = open_note(&mut connection, viewer, &fixture.note_id, Role::Viewer).await;
+        let vault = open_vault(&mut connection, viewer, &fixture.vault_id, Role::Editor).await;
+        let notebook = open_notebook(&mut connection, viewer, &fixture.notebook_id, Role::Editor).await;
+        let note = open_note(&mut connection, viewer, &fixture.note_id, Role::Editor).await;
+        let role = require_role(&mut connection, &viewer.user_id, &fixture.vault_id, Role::Editor).await;
+        cleanup(&mut connection, &fixture).await;
+
+        assert!(read.is_ok());
+        assert!(matches!(vault, Err(DbError::Forbidden)));
+        assert!(matches!(notebook, Err(DbError::Forbidden)));
+        assert!(matches!(note, Err(DbError::Forbidden)));
+        assert!(matches!(role, Err(DbError::Forbidden)));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis"]
+    async fn owner_can_open_everything() {
+        let mut connection = connect().await;
+        let fixture = fixture(&mut connection).await;
+        let owner = &fixture.owner;
+
+        let note = open_note(&mut connection, owner, &fixture.note_id, Role::Owner).await;
+        let role = require_role(&mut connection, &owner.user_id, &fixture.vault_id, Role::Owner).await;
+        cleanup(&mut connection, &fixture).await;
+
+        assert_eq!(note.map(|(notebook_id, _)| notebook_id).ok(), Some(fixture.notebook_id.clone()));
+        assert_eq!(role.ok(), Some(Role::Owner));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis"]
+    async fn unknown_ids_are_forbidden() {
+        let mut connection = connect().await;
+        let (keys, _) = user();
+        let missing = Uuid::new_v4().to_string();
+
+        let notebook = open_notebook(&mut connection, &keys, &missing, Role::Viewer).await;
+        let note = open_note(&mut connection, &keys, &missing, Role::Viewer).await;
+
+        assert!(matches!(notebook, Err(DbError::Forbidden)));
+        assert!(matches!(note, Err(DbError::Forbidden)));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis"]
+    async fn session_of_another_user_is_forbidden() {
+        let mut connection = connect().await;
+        let (owner, _) = user();
+        let (other, _) = user();
+
+        let token = generate_session_token(connection.clone(), owner.user_id.clone(), None).await.unwrap();
+        let own = authorize(&token, &owner).await;
+        let stolen = authorize(&token, &other).await;
+        let forged = authorize("user:not-a-session", &other).await;
+
+        let _: () = connection.del(&[token, format!("sessions:{}", owner.user_id)]).await.unwrap();
+
+        assert!(own.is_ok());
+        assert!(matches!(stolen, Err(DbError::Forbidden)));
+        assert!(matches!(forged, Err(DbError::Unauthorized)));
+    }
+
+    // the data commands themselves, not only the checks they call
+    #[tokio::test]
+    #[ignore = "needs redis"]
+    async fn commands_refuse_a_non_member() {
+        let mut connection = connect().await;
+        let fixture = fixture(&mut connection).await;
+        let stranger = &fixture.stranger;
+
+        let read = read_note(fixture.note_id.clone(), stranger).await;
+        let save = save_note(fixture.note_id.clone(), "overwritten".to_string(), stranger).await;
+        let notes = get_notes(fixture.notebook_id.clone(), stranger).await;
+        let notebook = create_notebook("Mine now".to_string(), fixture.vault_id.clone(), stranger).await;
+        let content = read_note(fixture.note_id.clone(), &fixture.owner).await;
+        cleanup(&mut connection, &fixture).await;
+
+        assert!(matches!(read, Err(DbError::Forbidden)));
+        assert!(matches!(save, Err(DbError::Forbidden)));
+        assert!(matches!(notes, Err(DbError::Forbidden)));
+        assert!(matches!(notebook, Err(DbError::Forbidden)));
+        assert_eq!(content.ok().as_deref(), Some("secret"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis"]
+    async fn commands_refuse_a_viewer_editing() {
+        let mut connection = connect().await;
+        let fixture = fixture(&mut connection).await;
+        let viewer = &fixture.viewer;
+
+        let read = read_note(fixture.note_id.clone(), viewer).await;
+        let save = save_note(fixture.note_id.clone(), "overwritten".to_string(), viewer).await;
+        let note = create_note("New".to_string(), fixture.notebook_id.clone(), viewer).await;
+        let vault = delete_vault(fixture.vault_id.clone(), viewer).await;
+        let content = read_note(fixture.note_id.clone(), &fixture.owner).await;
+        cleanup(&mut connection, &fixture).await;
+
+        assert_eq!(read.ok().as_deref(), Some("secret"));
+        assert!(matches!(save, Err(DbError::Forbidden)));
+        assert!(matches!(note, Err(DbError::Forbidden)));
+        assert!(matches!(vault, Err(DbError::Forbidden)));
+        assert_eq!(content.ok().as_deref(), Some("secret"));
+    }
+}
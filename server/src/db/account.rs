@@ -0,0 +1,180 @@
+use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+use super::client::{conn, env_seconds};
+use super::crypto::{generate_token, hash_token, UserKeys};
+use super::deletion::delete_unrecoverable_vaults;
+use super::error::{DbError, DbResult};
+use super::keys::{reset_user_keys, rewrap_data_key};
+use super::models::DeletionReport;
+use super::ops::get_user_id;
+use super::password::{hash_password, verify_password};
+use super::two_factor::{clear_two_factor, disable_two_factor, has_recovery_code, two_factor_enabled, verify_code};
+use super::sessions::{revoke_other_sessions, user_session_keys};
+use super::validation::{normalize_email, validate_password};
+use crate::notify::Notifier;
+
+// how long a reset token stays valid (default 30 minutes)
+fn reset_ttl() -> i64 {
+    env_seconds("PASSWORD_RESET_TTL", 1800)
+}
+
+// re-checks the password before sensitive account changes
+pub async fn check_password(connection: &mut MultiplexedConnection, user_id: &str, password: &str) -> DbResult<()> {
+    // get HASH
+    let stored: String = connection.hget(format!("user:{}", user_id), "password").await?;
+    if !verify_password(password, &stored) {
+        return Err(DbError::Invalid("Current password is incorrect".to_string()));
+    }
+
+    Ok(())
+}
+
+// CHANGE PASSWORD
+pub async fn change_user_password(current: String, new: String, session_token: &str, keys: &UserKeys) -> DbResult<()> {
+    validate_password(&new)?;
+
+    let mut connection = conn().await?;
+    let user_key = format!("user:{}", keys.user_id);
+
+    check_password(&mut connection, &keys.user_id, &current).await?;
+
+    let (salt, wrapped) = rewrap_data_key(keys, &new)?;
+
+    // set HASH (password and data key together, so they can never disagree)
+    let _: () = connection.hset_multiple(
+        &user_key,
+        &[("password", hash_password(&new)?), ("key_salt", salt), ("wrapped_key", wrapped)]
+    ).await?;
+
+    // other devices have to log in with the new password
+    revoke_other_sessions(session_token.to_string()).await?;
+
+    Ok(())
+}
+
+// REQUEST PASSWORD RESET
+// succeeds whether or not the email has an account, so it can't be used to find registered emails
+pub async fn create_reset_token(email: String, notifier: &dyn Notifier) -> DbResult<()> {
+    let mut connection = conn().await?;
+
+    let Some(user_id) = get_user_id(&mut connection, &email).await? else {
+        return Ok(());
+    };
+
+    let token = generate_token();
+    let token_hash = hash_token(&token);
+    let user_reset_key = format!("reset:{}", user_id);
+
+    // only the newest reset token works
+    let previous: Option<String> = connection.get(&user_reset_key).await?;
+    if let Some(previous) = previous {
+        let _: () = connection.del(format!("reset:{}", previous)).await?;
+    }
+
+    // create STRING (hashed token -> user, and user -> hashed token)
+    let ttl = reset_ttl() as u64;
+    let _: () = connection.set_ex(format!("reset:{}", token_hash), &user_id, ttl).await?;
+    let _: () = connection.set_ex(&user_reset_key, &token_hash, ttl).await?;
+
+    notifier
+        .send_password_reset(&normalize_email(&email), &token)
+        .await
+        .map_err(DbError::Other)
+}
+
+// the user a reset token belongs to (the token stays usable)
+async fn reset_token_user(connection: &mut MultiplexedConnection, token: &str) -> DbResult<String> {
+    // get STRING
+    let user_id: Option<String> = connection.get(format!("reset:{}", hash_token(token.trim()))).await?;
+    user_id.ok_or_else(|| DbError::Invalid("This reset token is invalid or has expired".to_string()))
+}
+
+// PREVIEW PASSWORD RESET
+// the vaults that can't be opened again after a reset, and what is deleted with them
+pub async fn preview_password_reset(token: String) -> DbResult<DeletionReport> {
+    let mut connection = conn().await?;
+
+    let user_id = reset_token_user(&mut connection, &token).await?;
+    delete_unrecoverable_vaults(&connection, &user_id, true).await
+}
+
+// RESET PASSWORD
+// encrypted data can't be recovered without the old password, so the account gets new keys
+// and every device is logged out; vaults no other owner can share again are deleted,
+// which has to be confirmed once the preview has listed them;
+// with two-factor enabled the reset link alone isn't enough, one of the recovery codes is needed too
+pub async fn reset_user_password(token: String, password: String, recovery_code: Option<String>, confirmed: bool) -> DbResult<DeletionReport> {
+    validate_password(&password)?;
+
+    let mut connection = conn().await?;
+    let token_key = format!("reset:{}", hash_token(token.trim()));
+    let recovery_code = recovery_code.unwrap_or_default();
+
+    let user_id = reset_token_user(&mut connection, &token).await?;
+    let preview = delete_unrecoverable_vaults(&connection, &user_id, true).await?;
+    let unrecoverable = preview.vaults_deleted + preview.vaults_left;
+    if unrecoverable > 0 && !confirmed {
+        return Err(DbError::Invalid(format!(
+            "{} vault(s) can only be opened with the old password and will be removed, confirm the reset to continue",
+            unrecoverable
+        )));
+    }
+
+    // the code is only checked here, it's used up with the rest of the reset
+    let two_factor = two_factor_enabled(&mut connection, &user_id).await?;
+    if two_factor && !has_recovery_code(&mut connection, &user_id, &recovery_code).await? {
+        return Err(DbError::Invalid("Two-factor authentication is enabled, enter one of your recovery codes".to_string()));
+    }
+
+    let report = delete_unrecoverable_vaults(&connection, &user_id, false).await?;
+    let password_hash = hash_password(&password)?;
+
+    // token, password, keys, two-factor and sessions change in one MULTI/EXEC, so a failure leaves the old
+    // account as it was and the token still usable; WATCH makes a second reset with the same token lose
+    loop {
+        let watched = [token_key.clone(), format!("recovery:{}", user_id), format!("sessions:{}", user_id), format!("vault:{}", user_id)];
+        let _: () = redis::cmd("WATCH").arg(&watched).query_async(&mut connection).await?;
+
+        // get STRING (used by another reset in the meantime)
+        let current: Option<String> = connection.get(&token_key).await?;
+        if current.as_deref() != Some(user_id.as_str()) {
+            let _: () = redis::cmd("UNWATCH").query_async(&mut connection).await?;
+            return Err(DbError::Invalid("This reset token is invalid or has expired".to_string()));
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+
+        // delete STRING (single use)
+        pipe.del(&[token_key.clone(), format!("reset:{}", user_id)]).ignore();
+
+        // set HASH
+        pipe.hset(format!("user:{}", user_id), "password", &password_hash).ignore();
+
+        reset_user_keys(&mut connection, &mut pipe, &user_id, &password).await?;
+
+        // the two-factor secret was encrypted under the old keys too, so it has to be set up again
+        // (clearing the recovery codes uses up the one given)
+        clear_two_factor(&mut pipe, &user_id);
+
+        // delete HASH (every session)
+        pipe.del(user_session_keys(&mut connection, &user_id).await?).ignore();
+
+        let applied: Option<()> = pipe.query_async(&mut connection).await?;
+        if applied.is_some() {
+            return Ok(report);
+        }
+    }
+}
+
+// DISABLE TWO-FACTOR (needs the password and a current or recovery code)
+pub async fn disable_user_two_factor(password: String, code: String, keys: &UserKeys) -> DbResult<()> {
+    let mut connection = conn().await?;
+
+    check_password(&mut connection, &keys.user_id, &password).await?;
+    if !verify_code(&mut connection, &code, keys).await? {
+        return Err(DbError::Invalid("Invalid code".to_string()));
+    }
+
+    Ok(disable_two_factor(&mut connection, &keys.user_id).await?)
+}
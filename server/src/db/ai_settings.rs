@@ -0,0 +1,78 @@
+use redis::AsyncCommands;
+use std::collections::HashMap;
+use super::client::conn;
+use super::crypto::{decrypt, encrypt, UserKeys};
+use super::error::DbResult;
+use super::validation::validate_ai_setting;
+use crate::llm::settings::{LlmSettings, SettingsSummary};
+
+// A user's own AI settings (bring your own key), the key is encrypted under their data key
+// so it is only readable while they are logged in
+
+// GET AI SETTINGS (decrypted, for building the user's providers)
+pub async fn get_user_ai_settings(keys: &UserKeys) -> DbResult<LlmSettings> {
+    let mut connection = conn().await?;
+
+    // get HASH
+    let stored: HashMap<String, String> = connection.hgetall(format!("ai_settings:{}", keys.user_id)).await?;
+
+    let mut settings = LlmSettings::default();
+    for (name, value) in stored {
+        let value = match name.as_str() {
+            "api_key" => decrypt(&keys.data_key, &value)?,
+            _ => value,
+        };
+        settings.set(&name, Some(value));
+    }
+
+    Ok(settings)
+}
+
+// overlays unsaved settings on the stored ones (an empty value clears that setting)
+pub async fn preview_user_ai_settings(changes: HashMap<String, String>, keys: &UserKeys) -> DbResult<LlmSettings> {
+    let mut settings = get_user_ai_settings(keys).await?;
+    for (name, value) in changes {
+        let value = validate_ai_setting(&name, &value)?;
+        settings.set(&name, value);
+    }
+
+    Ok(settings)
+}
+
+// UPDATE AI SETTINGS (an empty value clears that setting, returns what is now set)
+pub async fn update_user_ai_settings(changes: HashMap<String, String>, keys: &UserKeys) -> DbResult<SettingsSummary> {
+    let mut connection = conn().await?;
+    let settings_key = format!("ai_settings:{}", keys.user_id);
+
+    let mut set: Vec<(String, String)> = Vec::new();
+    let mut cleared: Vec<String> = Vec::new();
+
+    // everything is checked before anything is written
+    for (name, value) in changes {
+        match validate_ai_setting(&name, &value)? {
+            None => cleared.push(name),
+            Some(value) if name == "api_key" => set.push((name, encrypt(&keys.data_key, &value)?)),
+            Some(value) => set.push((name, value)),
+        }
+    }
+
+    // set + delete HASH fields
+    if !set.is_empty() {
+        let _: () = connection.hset_multiple(&settings_key, &set).await?;
+    }
+    if !cleared.is_empty() {
+        let _: () = connection.hdel(&settings_key, &cleared).await?;
+    }
+
+    Ok(get_user_ai_settings(keys).await?.summary())
+}
+
+// CLEAR AI SETTINGS (back to the server's own)
+pub async fn clear_user_ai_settings(keys: &UserKeys) -> DbResult<()> {
+    let mut connection = conn().await?;
+
+    // delete HASH
+    let _: () = connection.del(format!("ai_settings:{}", keys.user_id)).await?;
+
+    Ok(())
+}
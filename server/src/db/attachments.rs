@@ -0,0 +1,162 @@
+use redis::{AsyncCommands, RedisResult};
+use redis::aio::MultiplexedConnection;
+use std::collections::{HashMap, HashSet};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use super::client::conn;
+use super::crypto::{decrypt, decrypt_bytes, encrypt, encrypt_bytes, UserKeys};
+use super::access::{open_note, Role};
+use super::error::DbResult;
+use crate::attachments::{check_size, mime_type, sanitize_name};
+use uuid::Uuid;
+
+// binary chunk size, keeps individual redis values small
+const CHUNK_BYTES: usize = 512 * 1024;
+
+// prefix used to reference synced attachments from markdown, e.g. ![](attachment:<id>)
+pub const REFERENCE_PREFIX: &str = "attachment:";
+
+// extracts every attachment id referenced in a note
+pub fn referenced_ids(content: &str) -> HashSet<String> {
+    content
+        .match_indices(REFERENCE_PREFIX)
+        .filter_map(|(start, _)| {
+            let id = content.get(start + REFERENCE_PREFIX.len()..start + REFERENCE_PREFIX.len() + 36)?;
+            Uuid::parse_str(id).ok().map(|_| id.to_string())
+        })
+        .collect()
+}
+
+// removes an attachment's chunks, metadata and set membership
+async fn remove_attachment(connection: &mut MultiplexedConnection, id: &str, note_id: &str) -> RedisResult<()> {
+    let hash_key = format!("attachment:{}", id);
+    let chunks: usize = connection.hget(&hash_key, "chunks").await.unwrap_or(0);
+
+    // delete STRING chunks
+    for chunk in 0..chunks {
+        let _: () = connection.del(format!("attachment:{}:{}", id, chunk)).await?;
+    }
+
+    // delete HASH
+    let _: () = connection.del(&hash_key).await?;
+
+    // delete SET
+    let set_key = format!("attachment:{}", note_id);
+    let _: () = connection.srem(set_key, id).await?;
+
+    Ok(())
+}
+
+// CREATE ATTACHMENT
+pub async fn create_attachment(note_id: String, name: String, data: Vec<u8>, keys: &UserKeys) -> DbResult<String> {
+    let mut connection = conn().await?;
+
+    let (_, content_key) = open_note(&mut connection, keys, &note_id, Role::Editor).await?;
+
+    let set_key: String = format!("attachment:{}", note_id);
+
+    // total size already attached to the note
+    let existing_ids: Vec<String> = connection.smembers(&set_key).await?;
+    let mut existing: usize = 0;
+    for existing_id in existing_ids {
+        let size: usize = connection.hget(format!("attachment:{}", existing_id), "size").await?;
+        existing += size;
+    }
+
+    check_size(data.len(), existing)?;
+
+    let id: String = Uuid::new_v4().to_string();
+    let hash_key: String = format!("attachment:{}", id);
+    let name = sanitize_name(&name);
+    let chunks: Vec<&[u8]> = data.chunks(CHUNK_BYTES).collect();
+
+    // create STRING chunks (each encrypted on its own)
+    for (index, chunk) in chunks.iter().enumerate() {
+        let _: () = connection.set(format!("attachment:{}:{}", id, index), encrypt_bytes(&content_key, chunk)?).await?;
+    }
+
+    // create HASH
+    let _: () = connection.hset_multiple(
+        &hash_key,
+        &[
+            ("note_id", note_id),
+            ("mime", mime_type(&name).to_string()),
+            ("name", encrypt(&content_key, &name)?),
+            ("size", data.len().to_string()),
+            ("chunks", chunks.len().to_string()),
+        ]
+    ).await?;
+
+    // create SET
+    let _: () = connection.sadd(&set_key, &id).await?;
+
+    Ok(format!("{}{}", REFERENCE_PREFIX, id))
+}
+
+// GET ATTACHMENT (mime type and bytes)
+pub async fn read_attachment(attachment_id: String, keys: &UserKeys) -> DbResult<(String, Vec<u8>)> {
+    let mut connection = conn().await?;
+
+    let id = attachment_id.strip_prefix(REFERENCE_PREFIX).unwrap_or(&attachment_id);
+    let hash_key: String = format!("attachment:{}", id);
+
+    // get HASH
+    let (note_id, mime, chunks): (String, String, usize) = connection.hget(&hash_key, &["note_id", "mime", "chunks"]).await?;
+    let (_, content_key) = open_note(&mut connection, keys, &note_id, Role::Viewer).await?;
+
+    // get STRING chunks
+    let mut data: Vec<u8> = Vec::new();
+    for chunk in 0..chunks {
+        let bytes: Vec<u8> = connection.get(format!("attachment:{}:{}", id, chunk)).await?;
+        data.extend(decrypt_bytes(&content_key, &bytes)?);
+    }
+
+    Ok((mime, data))
+}
+
+// GET ATTACHMENT DATA URIS (used to inline images when exporting)
+pub async fn attachment_data_uris(content: &str, keys: &UserKeys) -> DbResult<HashMap<String, String>> {
+    let mut uris: HashMap<String, String> = HashMap::new();
+
+    for id in referenced_ids(content) {
+        let (mime, data) = read_attachment(id.clone(), keys).await?;
+        uris.insert(format!("{}{}", REFERENCE_PREFIX, id), format!("data:{};base64,{}", mime, STANDARD.encode(data)));
+    }
+
+    Ok(uris)
+}
+
+// DELETE UNREFERENCED ATTACHMENTS
+pub async fn collect_attachments(note_id: String, keys: &UserKeys) -> DbResult<Vec<String>> {
+    let mut connection = conn().await?;
+
+    let (_, content_key) = open_note(&mut connection, keys, &note_id, Role::Editor).await?;
+    let content: String = connection.hget(format!("note:{}", note_id), "content").await?;
+    let referenced = referenced_ids(&decrypt(&content_key, &content)?);
+
+    // get SET
+    let attachment_ids: Vec<String> = connection.smembers(format!("attachment:{}", note_id)).await?;
+
+    let mut removed: Vec<String> = Vec::new();
+    for id in attachment_ids {
+        if !referenced.contains(&id) {
+            remove_attachment(&mut connection, &id, &note_id).await?;
+            removed.push(format!("{}{}", REFERENCE_PREFIX, id));
+        }
+    }
+
+    Ok(removed)
+}
+
+// DELETE ALL NOTE ATTACHMENTS
+pub async fn delete_note_attachments(note_id: &str) -> RedisResult<()> {
+    let mut connection = conn().await?;
+
+    // get SET
+    let attachment_ids: Vec<String> = connection.smembers(format!("attachment:{}", note_id)).await?;
+
+    for id in attachment_ids {
+        remove_attachment(&mut connection, &id, note_id).await?;
+    }
+
+    Ok(())
+}
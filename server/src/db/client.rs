@@ -1,9 +1,11 @@
 use dotenvy_macro::dotenv;
 use redis::{AsyncCommands, Client, RedisResult};
 use redis::aio::MultiplexedConnection;
-use uuid::Uuid;
-use super::models::User;
+use super::models::{Profile, User};
+use super::error::DbResult;
+use super::sessions::session_user;
 use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 // grab DB url from .env file
 fn get_url() -> String {
@@ -21,68 +23,47 @@ pub async fn conn() -> redis::RedisResult<MultiplexedConnection> {
     client.get_multiplexed_async_connection().await
 }
 
-// gets user session token based on connection
-pub async fn generate_session_token(mut connection: MultiplexedConnection, user_id: String) -> RedisResult<String> {
-    let token = Uuid::new_v4().to_string();
-
-    let token_key = format!("session:{}", token);
-    let user_key = format!("session:{}", &user_id);
-    
-    // create STRING
-    let _: () = connection.set(&user_key, &token).await?;
-    // set EXPIRY
-    let _: () = connection.expire(&user_key, 28800).await?;
+// positive integer from an optional environment variable
+pub fn env_integer(name: &str, default: i64) -> i64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(default)
+}
 
-    // create HASH
-    let _: () = connection.hset_multiple(
-        &token_key,
-        &[("user_id", user_id)] 
-    ).await?;
-    // set EXPIRY
-    let _: () = connection.expire(&token_key, 28800).await?;
+// seconds from an optional environment variable
+pub fn env_seconds(name: &str, default: i64) -> i64 {
+    env_integer(name, default)
+}
 
-    Ok(token_key)
+// current unix time in seconds
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or(0)
 }
 
-pub async fn get_user_session_data(session_token: String) -> RedisResult<User> {
+// GET PROFILE (of the session's user)
+pub async fn get_user_profile(session_token: String) -> DbResult<Profile> {
     let mut connection = conn().await?;
 
-    let id: String = connection.hget(&session_token, "user_id").await?;
-
-    let user_id = format!("user:{}", id);
+    let id: String = session_user(&mut connection, &session_token).await?;
 
     // get HASH
-    let user_data: HashMap<String, String> = connection.hgetall(&user_id).await?;
+    let user_data: HashMap<String, String> = connection.hgetall(format!("user:{}", id)).await?;
+    let created_at: Option<i64> = user_data.get("created_at").and_then(|created_at| created_at.parse().ok());
 
     // assign hash to struct
     let account = User::assign(
         user_data.get("forename").cloned().unwrap(),
         user_data.get("email").cloned().unwrap(),
         user_data.get("password").cloned().unwrap()
-    );  
+    );
 
-    Ok(account)
-}
-
-pub async fn get_user_id(email: String) -> RedisResult<String> {
-    let mut connection = conn().await?;
-
-    let email_key: String = format!("user:{}", email);
+    // get HASH
+    let preferences: HashMap<String, String> = connection.hgetall(format!("preferences:{}", id)).await?;
 
-    // get STRING
-    let user_id: String = connection.get(email_key).await?;
-    
-    Ok(user_id)
+    Ok(account.into_profile(id, created_at, preferences))
 }
-
-pub async fn delete_session(token: String, user_id: String) -> RedisResult<bool> {
-    let mut connection = conn().await?;
-
-    let user_key = format!("session:{}", &user_id);
-
-    // delete STRING
-    let _: () = connection.del(&user_key).await?;
-    let _: () = connection.del(&token).await?;
-    
-    Ok(true)
-}   
\ No newline at end of file
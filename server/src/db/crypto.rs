@@ -0,0 +1,246 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use redis::{ErrorKind, RedisError, RedisResult};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+// prefix marking encrypted values, anything without it is refused
+const PREFIX: &str = "enc:v1:";
+// prefix marking vault keys sealed to a user's public key
+pub const SEALED_PREFIX: &str = "sealed:";
+const NONCE_BYTES: usize = 12;
+
+// 256-bit symmetric key (user data key, vault key or password-derived key)
+#[derive(Clone)]
+pub struct DataKey([u8; 32]);
+
+// Keys held in memory for the logged in user
+#[derive(Clone)]
+pub struct UserKeys {
+    pub user_id: String,
+    pub data_key: DataKey,
+    pub private_key: StaticSecret, // opens vault keys other users have shared
+}
+
+fn crypto_error(message: &'static str) -> RedisError {
+    RedisError::from((ErrorKind::ClientError, message))
+}
+
+// generates a random key
+pub fn generate_key() -> DataKey {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    DataKey(key)
+}
+
+// generates a random salt for password key derivation
+pub fn generate_salt() -> String {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    STANDARD.encode(salt)
+}
+
+// generates a random single-use token (e.g. password resets), sent to the user as hex
+pub fn generate_token() -> String {
+    let mut token = [0u8; 32];
+    OsRng.fill_bytes(&mut token);
+    hex::encode(token)
+}
+
+// hash of a token, only this is ever stored
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+// derives the key-encryption key from the user's password (Argon2id)
+pub fn derive_key(password: &str, salt: &str) -> RedisResult<DataKey> {
+    let salt = STANDARD.decode(salt).map_err(|_| crypto_error("Invalid key salt"))?;
+
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), &salt, &mut key)
+        .map_err(|_| crypto_error("Key derivation failed"))?;
+
+    Ok(DataKey(key))
+}
+
+// encrypts raw bytes, output is nonce || ciphertext
+pub fn encrypt_bytes(key: &DataKey, data: &[u8]) -> RedisResult<Vec<u8>> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.0));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let ciphertext = cipher.encrypt(&nonce, data).map_err(|_| crypto_error("Encryption failed"))?;
+
+    let mut output = nonce.to_vec();
+    output.extend(ciphertext);
+    Ok(output)
+}
+
+// decrypts and authenticates nonce || ciphertext
+pub fn decrypt_bytes(key: &DataKey, data: &[u8]) -> RedisResult<Vec<u8>> {
+    if data.len() < NONCE_BYTES {
+        return Err(crypto_error("Invalid ciphertext"));
+    }
+
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.0));
+    let (nonce, ciphertext) = data.split_at(NONCE_BYTES);
+
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| crypto_error("Decryption failed, data may have been tampered with"))
+}
+
+// encrypts a string value before it is written to redis
+pub fn encrypt(key: &DataKey, plaintext: &str) -> RedisResult<String> {
+    let ciphertext = encrypt_bytes(key, plaintext.as_bytes())?;
+    Ok(format!("{}{}", PREFIX, STANDARD.encode(ciphertext)))
+}
+
+// true for values written by encrypt
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+// decrypts a string value read from redis
+// unprefixed values are refused, plaintext written straight into redis must never pass as content
+pub fn decrypt(key: &DataKey, value: &str) -> RedisResult<String> {
+    let encoded = value.strip_prefix(PREFIX).ok_or_else(|| crypto_error("Value is not encrypted"))?;
+
+    let ciphertext = STANDARD.decode(encoded).map_err(|_| crypto_error("Invalid ciphertext"))?;
+    let plaintext = decrypt_bytes(key, &ciphertext)?;
+
+    String::from_utf8(plaintext).map_err(|_| crypto_error("Invalid ciphertext"))
+}
+
+// wraps (encrypts) a key under another key
+pub fn wrap_key(wrapping_key: &DataKey, key: &DataKey) -> RedisResult<String> {
+    Ok(STANDARD.encode(encrypt_bytes(wrapping_key, &key.0)?))
+}
+
+// unwraps a key, fails if the wrapping key is wrong
+pub fn unwrap_key(wrapping_key: &DataKey, wrapped: &str) -> RedisResult<DataKey> {
+    let wrapped = STANDARD.decode(wrapped).map_err(|_| crypto_error("Invalid wrapped key"))?;
+    let key: [u8; 32] = decrypt_bytes(wrapping_key, &wrapped)?
+        .try_into()
+        .map_err(|_| crypto_error("Invalid wrapped key"))?;
+
+    Ok(DataKey(key))
+}
+
+// keyed hash of a name, used in lookup keys so names never appear in plaintext
+pub fn blind_index(key: &DataKey, value: &str) -> String {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key.0).expect("HMAC accepts any key length");
+    mac.update(value.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// generates an X25519 key pair for receiving shared vault keys
+pub fn generate_key_pair() -> (StaticSecret, String) {
+    let secret = StaticSecret::from(generate_key().0);
+    let public = STANDARD.encode(PublicKey::from(&secret).as_bytes());
+    (secret, public)
+}
+
+// wraps a private key under the user's data key
+pub fn wrap_private_key(data_key: &DataKey, secret: &StaticSecret) -> RedisResult<String> {
+    wrap_key(data_key, &DataKey(secret.to_bytes()))
+}
+
+// unwraps a private key
+pub fn unwrap_private_key(data_key: &DataKey, wrapped: &str) -> RedisResult<StaticSecret> {
+    Ok(StaticSecret::from(unwrap_key(data_key, wrapped)?.0))
+}
+
+// key shared between an ephemeral and a recipient key pair
+fn seal_key_for(shared: &[u8], ephemeral: &[u8], recipient: &[u8]) -> DataKey {
+    let mut hasher = Sha256::new();
+    hasher.update(b"neuro-sealed-key");
+    hasher.update(shared);
+    hasher.update(ephemeral);
+    hasher.update(recipient);
+    DataKey(hasher.finalize().into())
+}
+
+// decodes a base64 public key
+fn decode_public_key(encoded: &str) -> RedisResult<PublicKey> {
+    let bytes: [u8; 32] = STANDARD
+        .decode(encoded)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| crypto_error("Invalid public key"))?;
+
+    Ok(PublicKey::from(bytes))
+}
+
+// seals a key so only the owner of `recipient` (base64 public key) can open it
+pub fn seal_key(recipient: &str, key: &DataKey) -> RedisResult<String> {
+    let recipient = decode_public_key(recipient)?;
+    let ephemeral = StaticSecret::from(generate_key().0);
+    let ephemeral_public = PublicKey::from(&ephemeral);
+
+    let shared = ephemeral.diffie_hellman(&recipient);
+    let sealing_key = seal_key_for(shared.as_bytes(), ephemeral_public.as_bytes(), recipient.as_bytes());
+
+    Ok(format!(
+        "{}{}:{}",
+        SEALED_PREFIX,
+        STANDARD.encode(ephemeral_public.as_bytes()),
+        wrap_key(&sealing_key, key)?
+    ))
+}
+
+// opens a key sealed with `seal_key`
+pub fn open_sealed_key(secret: &StaticSecret, sealed: &str) -> RedisResult<DataKey> {
+    let (ephemeral, wrapped) = sealed
+        .strip_prefix(SEALED_PREFIX)
+        .and_then(|sealed| sealed.split_once(':'))
+        .ok_or_else(|| crypto_error("Invalid sealed key"))?;
+
+    let ephemeral = decode_public_key(ephemeral)?;
+    let recipient = PublicKey::from(secret);
+
+    let shared = secret.diffie_hellman(&ephemeral);
+    let sealing_key = seal_key_for(shared.as_bytes(), ephemeral.as_bytes(), recipient.as_bytes());
+
+    unwrap_key(&sealing_key, wrapped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_round_trip() {
+        let key = generate_key();
+        let value = encrypt(&key, "My note").unwrap();
+
+        assert!(is_encrypted(&value));
+        assert_eq!(decrypt(&key, &value).unwrap(), "My note");
+        assert!(decrypt(&generate_key(), &value).is_err());
+    }
+
+    #[test]
+    fn plaintext_is_refused() {
+        let key = generate_key();
+
+        assert!(!is_encrypted("My note"));
+        assert!(decrypt(&key, "My note").is_err());
+        assert!(decrypt(&key, "").is_err());
+    }
+
+    #[test]
+    fn tampered_values_are_refused() {
+        let key = generate_key();
+        let value = encrypt(&key, "My note").unwrap();
+
+        let mut bytes = STANDARD.decode(value.strip_prefix(PREFIX).unwrap()).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        assert!(decrypt(&key, &format!("{}{}", PREFIX, STANDARD.encode(bytes))).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+use std::collections::HashMap;
+use super::access::{vault_role, Role};
+use super::account::check_password;
+use super::attachments::delete_note_attachments;
+use super::client::{conn, now};
+use super::crypto::{blind_index, decrypt, DataKey, UserKeys};
+use super::error::{DbError, DbResult};
+use super::keys::{vault_key, LOST_KEY};
+use super::models::DeletionReport;
+use super::ops::{note_index, release_name_key};
+use super::sessions::delete_user_sessions;
+use super::sharing::remove_member;
+use super::threads::delete_scope_threads;
+use super::embeddings::delete_note_embeddings;
+use super::flashcards::delete_deck;
+
+// Walks everything an account owns, removing it (or only counting it in a dry run)
+// every step re-reads what is left and removes children before the set that lists them,
+// so running it again after an interruption carries on where it stopped
+struct Cascade<'a> {
+    connection: MultiplexedConnection,
+    user_id: String,
+    keys: Option<&'a UserKeys>, // none after a password reset, the old keys are gone
+    report: DeletionReport,
+}
+
+// Implementation for cascade methods
+impl Cascade<'_> {
+    fn dry_run(&self) -> bool {
+        self.report.dry_run
+    }
+
+    // vaults are deleted when nobody else owns them, otherwise the user just leaves
+    async fn sole_owner(&mut self, vault_id: &str) -> DbResult<bool> {
+        let user_id = &self.user_id;
+        if vault_role(&mut self.connection, user_id, vault_id).await? != Some(Role::Owner) {
+            return Ok(false);
+        }
+
+        // get HASH
+        let members: HashMap<String, String> = self.connection.hgetall(format!("members:{}", vault_id)).await?;
+        Ok(!members.iter().any(|(member, role)| member != user_id && role == Role::Owner.as_str()))
+    }
+
+    // another owner still holds the vault key, so they can share it again after a password reset
+    async fn shared_key(&mut self, vault_id: &str) -> DbResult<bool> {
+        // get HASH (members and the keys they hold)
+        let members: HashMap<String, String> = self.connection.hgetall(format!("members:{}", vault_id)).await?;
+        let held: HashMap<String, String> = self.connection.hgetall(format!("vault_keys:{}", vault_id)).await?;
+
+        Ok(members.iter().any(|(member, role)| {
+            *member != self.user_id
+                && role == Role::Owner.as_str()
+                && held.get(member).is_some_and(|key| key != LOST_KEY)
+        }))
+    }
+
+    // name keys are blind indexes under the vault key, without it they can't be found
+    async fn content_key(&mut self, vault_id: &str) -> DbResult<Option<DataKey>> {
+        let Some(keys) = self.keys else {
+            return Ok(None);
+        };
+
+        match vault_key(&mut self.connection, keys, vault_id).await {
+            Ok(content_key) => Ok(Some(content_key)),
+            Err(DbError::Redis(e)) => Err(DbError::Redis(e)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    // decrypted name of a vault, notebook or note, if it's still there
+    async fn name(&mut self, hash_key: &str, content_key: Option<&DataKey>) -> DbResult<Option<String>> {
+        let Some(content_key) = content_key else {
+            return Ok(None);
+        };
+
+        // get HASH
+        let name: Option<String> = self.connection.hget(hash_key, "name").await?;
+        match name {
+            Some(name) => Ok(Some(decrypt(content_key, &name)?)),
+            None => Ok(None),
+        }
+    }
+
+    // DELETE NOTE (attachments, threads, embeddings, name key, hash, then set membership)
+    async fn note(&mut self, note_id: &str, notebook_id: &str, content_key: Option<&DataKey>) -> DbResult<()> {
+        let attachments: usize = self.connection.scard(format!("attachment:{}", note_id)).await?;
+        let threads: usize = self.connection.scard(format!("thread:{}", note_id)).await?;
+        self.report.attachments += attachments;
+        self.report.threads += threads;
+        self.report.notes += 1;
+        if self.dry_run() {
+            return Ok(());
+        }
+
+        delete_note_attachments(note_id).await?;
+        delete_scope_threads(&mut self.connection, note_id).await?;
+        delete_note_embeddings(&mut self.connection, note_id).await?;
+
+        let hash_key = format!("note:{}", note_id);
+        if let (Some(name), Some(content_key)) = (self.name(&hash_key, content_key).await?, content_key) {
+            // delete STRING
+            let _: () = self.connection.del(format!("note:{}", note_index(content_key, notebook_id, &name))).await?;
+        }
+
+        // delete HASH + SET member
+        let _: () = self.connection.del(&hash_key).await?;
+        let _: () = self.connection.srem(format!("note:{}", notebook_id), note_id).await?;
+
+        Ok(())
+    }
+
+    // DELETE NOTEBOOK (its notes first, then threads and flashcards)
+    async fn notebook(&mut self, notebook_id: &str, vault_id: &str, content_key: Option<&DataKey>) -> DbResult<()> {
+        // get SET
+        let note_ids: Vec<String> = self.connection.smembers(format!("note:{}", notebook_id)).await?;
+        for note_id in note_ids {
+            self.note(&note_id, notebook_id, content_key).await?;
+        }
+
+        let threads: usize = self.connection.scard(format!("thread:{}", notebook_id)).await?;
+        let flashcards: usize = self.connection.hlen(format!("deck:{}", notebook_id)).await?;
+        self.report.threads += threads;
+        self.report.flashcards += flashcards;
+        self.report.notebooks += 1;
+        if self.dry_run() {
+            return Ok(());
+        }
+
+        delete_scope_threads(&mut self.connection, notebook_id).await?;
+        delete_deck(&mut self.connection, notebook_id).await?;
+
+        let hash_key = format!("notebook:{}", notebook_id);
+        if let (Some(name), Some(content_key)) = (self.name(&hash_key, content_key).await?, content_key) {
+            // delete STRING
+            let _: () = self.connection.del(format!("notebook:{}", blind_index(content_key, &name))).await?;
+        }
+
+        // delete HASH + SET (the emptied note set) + SET member
+        let _: () = self.connection.del(&[&hash_key, &format!("note:{}", notebook_id)]).await?;
+        let _: () = self.connection.srem(format!("notebook:{}", vault_id), notebook_id).await?;
+
+        Ok(())
+    }
+
+    // DELETE VAULT (notebooks first, the vault leaves the user's own set last)
+    async fn vault(&mut self, vault_id: &str) -> DbResult<()> {
+        let content_key = self.content_key(vault_id).await?;
+
+        // get SET
+        let notebook_ids: Vec<String> = self.connection.smembers(format!("notebook:{}", vault_id)).await?;
+        for notebook_id in notebook_ids {
+            self.notebook(&notebook_id, vault_id, content_key.as_ref()).await?;
+        }
+
+        self.report.vaults_deleted += 1;
+        if self.dry_run() {
+            return Ok(());
+        }
+
+        let user_id = self.user_id.clone();
+        let hash_key = format!("vault:{}", vault_id);
+
+        // delete STRING (vaults created before the index only have the owner's name key)
+        if let (Some(name), Some(keys)) = (self.name(&hash_key, content_key.as_ref()).await?, self.keys) {
+            let name_key = format!("vault:{}", blind_index(&keys.data_key, &name));
+            release_name_key(&mut self.connection, &name_key, vault_id).await?;
+        }
+
+        // delete STRING per member name key, and the vault from every other member
+        let name_keys: Vec<String> = self.connection.hvals(format!("vault_index:{}", vault_id)).await?;
+        for name_key in name_keys {
+            release_name_key(&mut self.connection, &name_key, vault_id).await?;
+        }
+        let members: Vec<String> = self.connection.hkeys(format!("members:{}", vault_id)).await?;
+        for member in members.iter().filter(|member| **member != user_id) {
+            let _: () = self.connection.srem(format!("vault:{}", member), vault_id).await?;
+        }
+
+        // delete HASH (vault, keys, members, index) + SET (the emptied notebook set)
+        let _: () = self.connection.del(&[
+            hash_key,
+            format!("vault_keys:{}", vault_id),
+            format!("members:{}", vault_id),
+            format!("vault_index:{}", vault_id),
+            format!("notebook:{}", vault_id),
+        ]).await?;
+
+        // delete SET member
+        let _: () = self.connection.srem(format!("vault:{}", user_id), vault_id).await?;
+
+        Ok(())
+    }
+
+    // LEAVE VAULT (another owner keeps it)
+    async fn leave(&mut self, vault_id: &str) -> DbResult<()> {
+        self.report.vaults_left += 1;
+        if self.dry_run() {
+            return Ok(());
+        }
+
+        Ok(remove_member(&mut self.connection, vault_id, &self.user_id).await?)
+    }
+
+    // DELETE ACCOUNT RECORDS (sessions, preferences, AI settings, two-factor and reset keys, then the user)
+    async fn account(&mut self) -> DbResult<()> {
+        let user_id = self.user_id.clone();
+        let user_key = format!("user:{}", user_id);
+
+        let sessions: usize = self.connection.hlen(format!("sessions:{}", user_id)).await?;
+        self.report.sessions = sessions;
+        if self.dry_run() {
+            return Ok(());
+        }
+
+        delete_user_sessions(&mut self.connection, &user_id).await?;
+
+        // delete STRING (any outstanding reset token)
+        let reset_hash: Option<String> = self.connection.get(format!("reset:{}", user_id)).await?;
+        if let Some(reset_hash) = reset_hash {
+            let _: () = self.connection.del(format!("reset:{}", reset_hash)).await?;
+        }
+
+        // delete HASH + SET + STRING (the legacy session key too)
+        let _: () = self.connection.del(&[
+            format!("preferences:{}", user_id),
+            format!("ai_settings:{}", user_id),
+            format!("recovery:{}", user_id),
+            format!("reset:{}", user_id),
+            format!("vault:{}", user_id),
+            format!("session:{}", user_id),
+        ]).await?;
+
+        // get HASH
+        let email: Option<String> = self.connection.hget(&user_key, "email").await?;
+
+        // delete STRING + HASH together, so the email never points at a missing user
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(&user_key);
+        if let Some(email) = email {
+            let email_key = format!("user:{}", email);
+            let owner: Option<String> = self.connection.get(&email_key).await?;
+            if owner.as_deref() == Some(user_id.as_str()) {
+                pipe.del(email_key);
+            }
+        }
+        let _: () = pipe.query_async(&mut self.connection).await?;
+
+        Ok(())
+    }
+}
+
+// DELETE ACCOUNT
+// needs the password again; a dry run only reports what would be removed
+pub async fn delete_user_account(password: String, dry_run: bool, keys: &UserKeys) -> DbResult<DeletionReport> {
+    let mut connection = conn().await?;
+    let user_key = format!("user:{}", keys.user_id);
+
+    check_password(&mut connection, &keys.user_id, &password).await?;
+
+    // set HASH (marks the account as being deleted, kept from an interrupted run)
+    let resumed: bool = match dry_run {
+        true => connection.hexists(&user_key, "deleting").await?,
+        false => !connection.hset_nx::<_, _, _, bool>(&user_key, "deleting", now()).await?,
+    };
+
+    let mut cascade = Cascade {
+        connection,
+        user_id: keys.user_id.clone(),
+        keys: Some(keys),
+        report: DeletionReport { dry_run, resumed, ..Default::default() },
+    };
+
+    // get SET
+    let vault_ids: Vec<String> = cascade.connection.smembers(format!("vault:{}", keys.user_id)).await?;
+    for vault_id in vault_ids {
+        if cascade.sole_owner(&vault_id).await? {
+            cascade.vault(&vault_id).await?;
+        } else {
+            cascade.leave(&vault_id).await?;
+        }
+    }
+
+    cascade.account().await?;
+
+    Ok(cascade.report)
+}
+
+// DELETE UNRECOVERABLE VAULTS (password reset)
+// vaults no other owner holds a key for can't be opened again once the user's keys are reset,
+// so they're deleted (or left, if the user isn't their only owner) instead of being kept as unreadable data;
+// a dry run reports them so the reset can be confirmed first
+pub async fn delete_unrecoverable_vaults(connection: &MultiplexedConnection, user_id: &str, dry_run: bool) -> DbResult<DeletionReport> {
+    let mut cascade = Cascade {
+        connection: connection.clone(),
+        user_id: user_id.to_string(),
+        keys: None,
+        report: DeletionReport { dry_run, ..Default::default() },
+    };
+
+    // get SET
+    let vault_ids: Vec<String> = cascade.connection.smembers(format!("vault:{}", user_id)).await?;
+    for vault_id in vault_ids {
+        if cascade.shared_key(&vault_id).await? {
+            continue;
+        }
+
+        if cascade.sole_owner(&vault_id).await? {
+            cascade.vault(&vault_id).await?;
+        } else {
+            cascade.leave(&vault_id).await?;
+        }
+    }
+
+    Ok(cascade.report)
+}
@@ -0,0 +1,132 @@
+use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+use std::collections::HashSet;
+use super::access::{open_note, open_scope, Role};
+use super::client::conn;
+use super::crypto::{blind_index, decrypt, encrypt, DataKey, UserKeys};
+use super::error::{DbError, DbResult};
+use super::ops::get_scope_notes;
+use crate::llm::chunks::{chunk_markdown, Chunk};
+use crate::llm::provider::LlmProvider;
+use crate::llm::vectors::{chunk_fingerprint, embed_chunks, plan_update, EmbeddedChunk, SearchHit, VectorIndex};
+
+// index key of a chunk, keyed under the vault key so equal text in other vaults can't be matched up
+fn chunk_key(content_key: &DataKey, model: &str, chunk: &Chunk) -> String {
+    blind_index(content_key, &chunk_fingerprint(model, chunk))
+}
+
+// index of a note for one embedding model, members searching with other models get their own
+fn index_key(model: &str, note_id: &str) -> String {
+    format!("embedding:{}:{}", model, note_id)
+}
+
+// SET of the models a note has an index for, so they can all be removed with it
+fn models_key(note_id: &str) -> String {
+    format!("embedding_models:{}", note_id)
+}
+
+fn json_error(e: serde_json::Error) -> DbError {
+    DbError::Other(e.to_string())
+}
+
+// INDEX NOTE (only chunks that changed since the last time are embedded), returns how many were
+async fn index_note(connection: &mut MultiplexedConnection, note_id: &str, content: &str, content_key: &DataKey, provider: &dyn LlmProvider) -> DbResult<usize> {
+    let model = provider.embedding_model();
+    let index_key = index_key(&model, note_id);
+
+    let chunks: Vec<(String, Chunk)> = chunk_markdown(content)
+        .into_iter()
+        .map(|chunk| (chunk_key(content_key, &model, &chunk), chunk))
+        .collect();
+
+    // get HASH fields
+    let existing: HashSet<String> = connection.hkeys(&index_key).await?;
+    let update = plan_update(&existing, chunks);
+    let embedded = embed_chunks(provider, update.embed).await?;
+
+    // set HASH (encrypted like the note itself, vectors say a lot about the text)
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for (key, chunk) in &embedded {
+        fields.push((key.clone(), encrypt(content_key, &serde_json::to_string(chunk).map_err(json_error)?)?));
+    }
+    if !fields.is_empty() {
+        let _: () = connection.hset_multiple(&index_key, &fields).await?;
+
+        // create SET member
+        let _: () = connection.sadd(models_key(note_id), &model).await?;
+    }
+
+    // delete HASH fields (chunks that were edited or removed)
+    if !update.stale.is_empty() {
+        let _: () = connection.hdel(&index_key, &update.stale).await?;
+    }
+
+    Ok(embedded.len())
+}
+
+// INDEX SAVED NOTE (called after a save)
+pub async fn index_saved_note(note_id: String, keys: &UserKeys, provider: &dyn LlmProvider) -> DbResult<usize> {
+    let mut connection = conn().await?;
+
+    let (_, content_key) = open_note(&mut connection, keys, &note_id, Role::Editor).await?;
+
+    // get HASH
+    let content: String = connection.hget(format!("note:{}", note_id), "content").await?;
+    index_note(&mut connection, &note_id, &decrypt(&content_key, &content)?, &content_key, provider).await
+}
+
+// SEMANTIC SEARCH ("note:<id>", "notebook:<id>" or "vault:<id>", closest chunks first)
+// editors bring notes saved before indexing up to date first, viewers only read what is already indexed
+pub async fn search_notes(scope: String, query: String, top_k: usize, provider: &dyn LlmProvider, keys: &UserKeys) -> DbResult<Vec<SearchHit>> {
+    let mut connection = conn().await?;
+
+    let content_key = open_scope(&mut connection, keys, &scope, Role::Viewer).await?;
+    let can_index = match open_scope(&mut connection, keys, &scope, Role::Editor).await {
+        Ok(_) => true,
+        Err(DbError::Forbidden) => false,
+        Err(e) => return Err(e),
+    };
+    let notes = get_scope_notes(scope, keys).await?;
+    let model = provider.embedding_model();
+
+    let mut index: VectorIndex<(String, String, String, String)> = VectorIndex::default();
+    for (note_id, name, content) in notes {
+        if can_index {
+            index_note(&mut connection, &note_id, &content, &content_key, provider).await?;
+        }
+
+        // get HASH
+        let entries: Vec<String> = connection.hvals(index_key(&model, &note_id)).await?;
+        for entry in entries {
+            let chunk: EmbeddedChunk = serde_json::from_str(&decrypt(&content_key, &entry)?).map_err(json_error)?;
+            index.insert((note_id.clone(), name.clone(), chunk.heading, chunk.text), chunk.vector);
+        }
+    }
+
+    let query = provider.embed(&[query]).await?;
+    let query = query.first().ok_or_else(|| DbError::Other("The provider returned no embedding".to_string()))?;
+
+    Ok(index
+        .search(query, top_k)
+        .into_iter()
+        .map(|(score, (note_id, note, heading, text))| SearchHit { note_id, note, heading, text, score })
+        .collect())
+}
+
+// EMBEDDING KEYS (every index a note has and the set listing them)
+pub async fn embedding_keys(connection: &mut MultiplexedConnection, note_id: &str) -> redis::RedisResult<Vec<String>> {
+    // get SET
+    let models: Vec<String> = connection.smembers(models_key(note_id)).await?;
+
+    let mut keys: Vec<String> = models.iter().map(|model| index_key(model, note_id)).collect();
+    keys.push(models_key(note_id));
+    Ok(keys)
+}
+
+// DELETE NOTE EMBEDDINGS
+pub async fn delete_note_embeddings(connection: &mut MultiplexedConnection, note_id: &str) -> redis::RedisResult<()> {
+    let keys = embedding_keys(connection, note_id).await?;
+
+    // delete HASH + SET
+    connection.del(keys).await
+}
@@ -0,0 +1,100 @@
+use redis::RedisError;
+use crate::llm::error::LlmError;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+// Errors returned by database queries
+#[derive(Debug)]
+pub enum DbError {
+    Redis(RedisError),
+    InvalidCredentials,
+    TooManyAttempts(u64), // login throttled, seconds until the next attempt is allowed
+    Unauthorized,         // not logged in, or the session is invalid
+    SessionExpired,       // idle timeout or absolute lifetime reached
+    Forbidden,            // session is valid but the user can't touch this data
+    EmailTaken,           // registration with an email that already has an account
+    Invalid(String),      // input rejected by validation
+    KeyLost,              // vault key was lost in a password reset
+    Ai(LlmError),         // the AI provider failed, keeps its own kind and retry_after
+    Other(String),
+}
+
+pub type DbResult<T> = Result<T, DbError>;
+
+// Implementation for error methods
+impl DbError {
+    // short name the frontend can match on
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DbError::Redis(_) => "database",
+            DbError::InvalidCredentials => "invalid_credentials",
+            DbError::TooManyAttempts(_) => "too_many_attempts",
+            DbError::Unauthorized => "unauthorized",
+            DbError::SessionExpired => "session_expired",
+            DbError::Forbidden => "forbidden",
+            DbError::EmailTaken => "email_taken",
+            DbError::Invalid(_) => "invalid",
+            DbError::KeyLost => "key_lost",
+            DbError::Ai(e) => e.kind(),
+            DbError::Other(_) => "error",
+        }
+    }
+
+    // seconds the frontend should wait before retrying, if any
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            DbError::TooManyAttempts(seconds) => Some(*seconds),
+            DbError::Ai(e) => e.retry_after(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Redis(e) => write!(f, "{}", e),
+            DbError::InvalidCredentials => write!(f, "Invalid Credentials"),
+            DbError::TooManyAttempts(seconds) => write!(f, "Too many attempts, retry after {} seconds", seconds),
+            DbError::Unauthorized => write!(f, "Invalid session, please log in again"),
+            DbError::SessionExpired => write!(f, "Session expired, please log in again"),
+            DbError::Forbidden => write!(f, "Forbidden"),
+            DbError::EmailTaken => write!(f, "An account is already registered with that email"),
+            DbError::Invalid(message) => write!(f, "{}", message),
+            DbError::KeyLost => write!(f, "This vault's key was lost in a password reset, ask another owner to share it again"),
+            DbError::Ai(e) => write!(f, "{}", e),
+            DbError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<RedisError> for DbError {
+    fn from(error: RedisError) -> Self {
+        DbError::Redis(error)
+    }
+}
+
+impl From<LlmError> for DbError {
+    fn from(error: LlmError) -> Self {
+        DbError::Ai(error)
+    }
+}
+
+impl From<String> for DbError {
+    fn from(message: String) -> Self {
+        DbError::Other(message)
+    }
+}
+
+// sent to the frontend as { kind, message, retry_after }
+impl Serialize for DbError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("DbError", 3)?;
+        error.serialize_field("kind", self.kind())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.serialize_field("retry_after", &self.retry_after())?;
+        error.end()
+    }
+}
@@ -0,0 +1,204 @@
+use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+use redis::RedisResult;
+use std::collections::HashMap;
+use super::access::{open_note, open_notebook, Role};
+use super::client::{conn, now};
+use super::crypto::{decrypt, encrypt, DataKey, UserKeys};
+use super::error::{DbError, DbResult};
+use crate::llm::flashcards::{due_cards, generate_flashcards, study_day, Flashcard, Schedule, StudyCard, GRADES};
+use crate::llm::provider::LlmProvider;
+
+// Decks are shared by everyone with the notebook, review schedules are per user
+// deck:{notebook_id} HASH card id -> encrypted card, reviews:{notebook_id} HASH "{user_id}:{card_id}" -> schedule
+
+fn json_error(e: serde_json::Error) -> DbError {
+    DbError::Other(e.to_string())
+}
+
+fn review_field(user_id: &str, card_id: &str) -> String {
+    format!("{}:{}", user_id, card_id)
+}
+
+// every card in a notebook's deck, oldest first
+async fn load_deck(connection: &mut MultiplexedConnection, notebook_id: &str, content_key: &DataKey) -> DbResult<Vec<Flashcard>> {
+    // get HASH
+    let encrypted: Vec<String> = connection.hvals(format!("deck:{}", notebook_id)).await?;
+
+    let mut cards: Vec<Flashcard> = Vec::new();
+    for card in encrypted {
+        cards.push(serde_json::from_str(&decrypt(content_key, &card)?).map_err(json_error)?);
+    }
+    cards.sort_by_key(|card| card.created_at);
+
+    Ok(cards)
+}
+
+// the user's schedules for the given cards
+async fn load_schedules(connection: &mut MultiplexedConnection, notebook_id: &str, user_id: &str, cards: &[Flashcard]) -> DbResult<HashMap<String, Schedule>> {
+    if cards.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    // get HASH fields
+    let fields: Vec<String> = cards.iter().map(|card| review_field(user_id, &card.id)).collect();
+    let stored: Vec<Option<String>> = redis::cmd("HMGET")
+        .arg(format!("reviews:{}", notebook_id))
+        .arg(&fields)
+        .query_async(connection)
+        .await?;
+
+    let mut schedules: HashMap<String, Schedule> = HashMap::new();
+    for (card, schedule) in cards.iter().zip(stored) {
+        if let Some(schedule) = schedule {
+            schedules.insert(card.id.clone(), serde_json::from_str(&schedule).map_err(json_error)?);
+        }
+    }
+
+    Ok(schedules)
+}
+
+// CREATE FLASHCARDS (generated from a note into its notebook's deck), returns the new cards
+pub async fn create_flashcards(note_id: String, count: usize, provider: &dyn LlmProvider, model: Option<String>, keys: &UserKeys) -> DbResult<Vec<Flashcard>> {
+    let mut connection = conn().await?;
+
+    let (notebook_id, content_key) = open_note(&mut connection, keys, &note_id, Role::Editor).await?;
+
+    // get HASH
+    let content: String = connection.hget(format!("note:{}", note_id), "content").await?;
+    let content = decrypt(&content_key, &content)?;
+    if content.trim().is_empty() {
+        return Err(DbError::Invalid("This note is empty".to_string()));
+    }
+
+    let existing = load_deck(&mut connection, &notebook_id, &content_key).await?;
+    let cards = generate_flashcards(&note_id, &content, count, &existing, provider, model, now()).await?;
+
+    // set HASH
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for card in &cards {
+        fields.push((card.id.clone(), encrypt(&content_key, &serde_json::to_string(card).map_err(json_error)?)?));
+    }
+    if !fields.is_empty() {
+        let _: () = connection.hset_multiple(format!("deck:{}", notebook_id), &fields).await?;
+    }
+
+    Ok(cards)
+}
+
+// GET DECK (every card with the user's schedule for it)
+pub async fn get_deck(notebook_id: String, keys: &UserKeys) -> DbResult<Vec<StudyCard>> {
+    let mut connection = conn().await?;
+
+    let (_, content_key) = open_notebook(&mut connection, keys, &notebook_id, Role::Viewer).await?;
+    let cards = load_deck(&mut connection, &notebook_id, &content_key).await?;
+    let mut schedules = load_schedules(&mut connection, &notebook_id, &keys.user_id, &cards).await?;
+
+    Ok(cards
+        .into_iter()
+        .map(|card| {
+            let schedule = schedules.remove(&card.id).unwrap_or_default();
+            StudyCard { card, schedule }
+        })
+        .collect())
+}
+
+// GET DUE CARDS (due today or earlier in the user's timezone)
+pub async fn get_due_cards(notebook_id: String, utc_offset_minutes: i32, limit: Option<usize>, keys: &UserKeys) -> DbResult<Vec<StudyCard>> {
+    let mut connection = conn().await?;
+
+    let (_, content_key) = open_notebook(&mut connection, keys, &notebook_id, Role::Viewer).await?;
+    let cards = load_deck(&mut connection, &notebook_id, &content_key).await?;
+    let schedules = load_schedules(&mut connection, &notebook_id, &keys.user_id, &cards).await?;
+
+    Ok(due_cards(cards, &schedules, study_day(now(), utc_offset_minutes), limit))
+}
+
+// REVIEW CARD (records the grade, returns when the card is next due)
+pub async fn review_card(notebook_id: String, card_id: String, grade: u8, utc_offset_minutes: i32, keys: &UserKeys) -> DbResult<Schedule> {
+    if !GRADES.contains(&grade) {
+        return Err(DbError::Invalid(format!("Grade must be between {} and {}", GRADES.start(), GRADES.end())));
+    }
+
+    let mut connection = conn().await?;
+
+    // viewers study too, the schedule is their own
+    open_notebook(&mut connection, keys, &notebook_id, Role::Viewer).await?;
+
+    let exists: bool = connection.hexists(format!("deck:{}", notebook_id), &card_id).await?;
+    if !exists {
+        return Err(DbError::Invalid("Card not found".to_string()));
+    }
+
+    let reviews_key = format!("reviews:{}", notebook_id);
+    let field = review_field(&keys.user_id, &card_id);
+
+    // get + set HASH field, retried if another review of the deck is saved in between
+    // (WATCH covers the whole hash, so two devices grading the same card can't both build on the old schedule)
+    loop {
+        let _: () = redis::cmd("WATCH").arg(&reviews_key).query_async(&mut connection).await?;
+
+        let stored: Option<String> = connection.hget(&reviews_key, &field).await?;
+        let schedule = match stored {
+            Some(stored) => serde_json::from_str(&stored).map_err(json_error)?,
+            None => Schedule::default(),
+        };
+
+        let now = now();
+        let schedule = schedule.review(grade, study_day(now, utc_offset_minutes), now);
+
+        let mut pipe = redis::pipe();
+        pipe.atomic().hset(&reviews_key, &field, serde_json::to_string(&schedule).map_err(json_error)?).ignore();
+
+        let applied: Option<()> = pipe.query_async(&mut connection).await?;
+        if applied.is_some() {
+            return Ok(schedule);
+        }
+    }
+}
+
+// DELETE CARD (for everyone studying the deck)
+pub async fn delete_card(notebook_id: String, card_id: String, keys: &UserKeys) -> DbResult<()> {
+    let mut connection = conn().await?;
+
+    open_notebook(&mut connection, keys, &notebook_id, Role::Editor).await?;
+
+    // delete HASH field, then every user's schedule for it
+    let _: () = connection.hdel(format!("deck:{}", notebook_id), &card_id).await?;
+
+    let reviews_key = format!("reviews:{}", notebook_id);
+    let suffix = format!(":{}", card_id);
+    let fields: Vec<String> = connection.hkeys(&reviews_key).await?;
+    let stale: Vec<String> = fields.into_iter().filter(|field| field.ends_with(&suffix)).collect();
+    if !stale.is_empty() {
+        let _: () = connection.hdel(&reviews_key, &stale).await?;
+    }
+
+    Ok(())
+}
+
+// DELETE DECK (when its notebook is deleted), returns how many cards went
+pub async fn delete_deck(connection: &mut MultiplexedConnection, notebook_id: &str) -> RedisResult<usize> {
+    let deck_key = format!("deck:{}", notebook_id);
+    let cards: usize = connection.hlen(&deck_key).await?;
+
+    // delete HASH (cards + schedules)
+    let _: () = connection.del(&[deck_key, format!("reviews:{}", notebook_id)]).await?;
+
+    Ok(cards)
+}
+
+// DELETE USER REVIEWS (a user leaving a vault takes their schedules with them)
+pub async fn delete_user_reviews(connection: &mut MultiplexedConnection, notebook_id: &str, user_id: &str) -> RedisResult<()> {
+    let reviews_key = format!("reviews:{}", notebook_id);
+    let prefix = format!("{}:", user_id);
+
+    // get HASH keys, delete the user's fields
+    let fields: Vec<String> = connection.hkeys(&reviews_key).await?;
+    let own: Vec<String> = fields.into_iter().filter(|field| field.starts_with(&prefix)).collect();
+    if !own.is_empty() {
+        let _: () = connection.hdel(&reviews_key, &own).await?;
+    }
+
+    Ok(())
+}
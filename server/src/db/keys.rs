@@ -0,0 +1,208 @@
+use redis::{AsyncCommands, Pipeline, RedisResult};
+use redis::aio::MultiplexedConnection;
+use super::error::{DbError, DbResult};
+use super::crypto::{
+    derive_key, encrypt, generate_key, generate_key_pair, generate_salt, is_encrypted, open_sealed_key, unwrap_key,
+    unwrap_private_key, wrap_key, wrap_private_key, DataKey, UserKeys, SEALED_PREFIX,
+};
+
+// marks vault keys that were wrapped under a data key lost in a password reset
+pub const LOST_KEY: &str = "lost";
+
+// UNLOCK USER KEYS
+// derives the password key and unwraps the user's data key, called on login
+pub async fn unlock_user_keys(connection: &mut MultiplexedConnection, user_id: &str, password: &str) -> RedisResult<UserKeys> {
+    let user_key = format!("user:{}", user_id);
+
+    // get HASH
+    let (salt, wrapped): (Option<String>, Option<String>) = connection.hget(&user_key, &["key_salt", "wrapped_key"]).await?;
+
+    let data_key = match (salt, wrapped) {
+        (Some(salt), Some(wrapped)) => unwrap_key(&derive_key(password, &salt)?, &wrapped)?,
+
+        // accounts created before encryption get their data key on first login
+        _ => {
+            let salt = generate_salt();
+            let data_key = generate_key();
+            let wrapped = wrap_key(&derive_key(password, &salt)?, &data_key)?;
+
+            // set HASH
+            let _: () = connection.hset_multiple(
+                &user_key,
+                &[("key_salt", salt), ("wrapped_key", wrapped)]
+            ).await?;
+
+            data_key
+        }
+    };
+
+    // get HASH
+    let wrapped_private: Option<String> = connection.hget(&user_key, "wrapped_private_key").await?;
+
+    let private_key = match wrapped_private {
+        Some(wrapped) => unwrap_private_key(&data_key, &wrapped)?,
+
+        // key pair used to receive shared vaults, created on first login
+        None => {
+            let (private_key, public_key) = generate_key_pair();
+
+            // set HASH
+            let _: () = connection.hset_multiple(
+                &user_key,
+                &[("public_key", public_key), ("wrapped_private_key", wrap_private_key(&data_key, &private_key)?)]
+            ).await?;
+
+            private_key
+        }
+    };
+
+    Ok(UserKeys { user_id: user_id.to_string(), data_key, private_key })
+}
+
+// CREATE VAULT KEY
+// every vault has its own content key, wrapped under the data key of each user holding it
+pub async fn create_vault_key(connection: &mut MultiplexedConnection, keys: &UserKeys, vault_id: &str) -> RedisResult<DataKey> {
+    let keys_key = format!("vault_keys:{}", vault_id);
+    let vault_key = generate_key();
+    let wrapped = wrap_key(&keys.data_key, &vault_key)?;
+
+    // create HASH field (only if it's not there yet, two devices opening the same vault must agree on its key)
+    let created: bool = connection.hset_nx(&keys_key, &keys.user_id, wrapped).await?;
+    if created {
+        return Ok(vault_key);
+    }
+
+    // the other open won, use the key it stored
+    let stored: String = connection.hget(&keys_key, &keys.user_id).await?;
+    unwrap_key(&keys.data_key, &stored)
+}
+
+// queues encrypting the plaintext fields of a hash
+async fn encrypt_fields(connection: &mut MultiplexedConnection, pipe: &mut redis::Pipeline, key: &DataKey, hash_key: &str, fields: &[&str]) -> RedisResult<()> {
+    // get HASH fields
+    let values: Vec<Option<String>> = redis::cmd("HMGET").arg(hash_key).arg(fields).query_async(connection).await?;
+
+    for (field, value) in fields.iter().zip(values) {
+        if let Some(value) = value.filter(|value| !is_encrypted(value)) {
+            pipe.hset(hash_key, *field, encrypt(key, &value)?).ignore();
+        }
+    }
+    Ok(())
+}
+
+// ENCRYPT LEGACY VAULT
+// one-off migration of a vault created before encryption: its names and note content are encrypted
+// under a new vault key in the same transaction that stores the key, so nothing is ever read as plaintext;
+// WATCH on the vault's keys makes two devices opening it at once agree on a single key
+async fn encrypt_legacy_vault(connection: &mut MultiplexedConnection, keys: &UserKeys, vault_id: &str) -> RedisResult<DataKey> {
+    let keys_key = format!("vault_keys:{}", vault_id);
+
+    loop {
+        let _: () = redis::cmd("WATCH").arg(&keys_key).query_async(connection).await?;
+
+        // the other open won, use the key it stored
+        let stored: Option<String> = connection.hget(&keys_key, &keys.user_id).await?;
+        if let Some(stored) = stored {
+            let _: () = redis::cmd("UNWATCH").query_async(connection).await?;
+            return unwrap_key(&keys.data_key, &stored);
+        }
+
+        let vault_key = generate_key();
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        pipe.hset(&keys_key, &keys.user_id, wrap_key(&keys.data_key, &vault_key)?).ignore();
+
+        encrypt_fields(connection, &mut pipe, &vault_key, &format!("vault:{}", vault_id), &["name"]).await?;
+
+        // get SET
+        let notebook_ids: Vec<String> = connection.smembers(format!("notebook:{}", vault_id)).await?;
+        for notebook_id in notebook_ids {
+            encrypt_fields(connection, &mut pipe, &vault_key, &format!("notebook:{}", notebook_id), &["name"]).await?;
+
+            // get SET
+            let note_ids: Vec<String> = connection.smembers(format!("note:{}", notebook_id)).await?;
+            for note_id in note_ids {
+                encrypt_fields(connection, &mut pipe, &vault_key, &format!("note:{}", note_id), &["name", "content"]).await?;
+            }
+        }
+
+        // EXEC returns nil when the key was created in the meantime, go round again and use it
+        let applied: Option<()> = pipe.query_async(connection).await?;
+        if applied.is_some() {
+            return Ok(vault_key);
+        }
+    }
+}
+
+// GET VAULT KEY
+pub async fn vault_key(connection: &mut MultiplexedConnection, keys: &UserKeys, vault_id: &str) -> DbResult<DataKey> {
+    // get HASH
+    let wrapped: Option<String> = connection.hget(format!("vault_keys:{}", vault_id), &keys.user_id).await?;
+
+    if let Some(wrapped) = wrapped {
+        if wrapped == LOST_KEY {
+            return Err(DbError::KeyLost);
+        }
+
+        if !wrapped.starts_with(SEALED_PREFIX) {
+            return Ok(unwrap_key(&keys.data_key, &wrapped)?);
+        }
+
+        // key shared by another user, re-wrap it under our own data key on first use
+        let vault_key = open_sealed_key(&keys.private_key, &wrapped)?;
+        let rewrapped = wrap_key(&keys.data_key, &vault_key)?;
+        let _: () = connection.hset(format!("vault_keys:{}", vault_id), &keys.user_id, rewrapped).await?;
+
+        return Ok(vault_key);
+    }
+
+    // vaults created before encryption get a key when their owner first opens them
+    let owner: Option<String> = connection.hget(format!("vault:{}", vault_id), "user_id").await?;
+    if owner.as_deref() == Some(keys.user_id.as_str()) {
+        return Ok(encrypt_legacy_vault(connection, keys, vault_id).await?);
+    }
+
+    Err(DbError::Other("Vault key not available".to_string()))
+}
+
+// REWRAP DATA KEY (password change, returns the new key_salt and wrapped_key fields)
+// the data key itself stays the same, so nothing else needs re-encrypting
+pub fn rewrap_data_key(keys: &UserKeys, password: &str) -> RedisResult<(String, String)> {
+    let salt = generate_salt();
+    let wrapped = wrap_key(&derive_key(password, &salt)?, &keys.data_key)?;
+    Ok((salt, wrapped))
+}
+
+// RESET USER KEYS (queued on `pipe`, so they're written together with the new password)
+// a forgotten password means the data key can't be unwrapped, so the user starts over with new keys
+// under the new password, and every vault key they held is marked lost until another owner shares it again
+pub async fn reset_user_keys(connection: &mut MultiplexedConnection, pipe: &mut Pipeline, user_id: &str, password: &str) -> RedisResult<()> {
+    let salt = generate_salt();
+    let data_key = generate_key();
+    let wrapped = wrap_key(&derive_key(password, &salt)?, &data_key)?;
+    let (private_key, public_key) = generate_key_pair();
+
+    // set HASH fields
+    pipe.hset_multiple(
+        format!("user:{}", user_id),
+        &[
+            ("key_salt", salt),
+            ("wrapped_key", wrapped),
+            ("public_key", public_key),
+            ("wrapped_private_key", wrap_private_key(&data_key, &private_key)?),
+        ],
+    ).ignore();
+
+    // get SET
+    let vault_ids: Vec<String> = connection.smembers(format!("vault:{}", user_id)).await?;
+
+    for vault_id in vault_ids {
+        let keys_key = format!("vault_keys:{}", vault_id);
+        let held: bool = connection.hexists(&keys_key, user_id).await?;
+        if held {
+            pipe.hset(&keys_key, user_id, LOST_KEY).ignore();
+        }
+    }
+
+    Ok(())
+}
@@ -1,3 +1,22 @@
 pub mod client;
 pub mod ops;
-pub mod models;
\ No newline at end of file
+pub mod models;
+pub mod attachments;
+pub mod crypto;
+pub mod keys;
+pub mod access;
+pub mod sharing;
+pub mod error;
+pub mod sessions;
+pub mod validation;
+pub mod account;
+pub mod throttle;
+pub mod two_factor;
+pub mod deletion;
+pub mod profile;
+pub mod password;
+pub mod threads;
+pub mod embeddings;
+pub mod ai_settings;
+pub mod flashcards;
+pub mod rotation;
@@ -1,7 +1,8 @@
-use bcrypt::verify;
+use super::password;
+use std::collections::HashMap;
 
-// User structure
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+// User structure (internal only, holds the password hash so it's never serialized)
+#[derive(Debug)]
 pub struct User {
     forename: String,
     email: String,
@@ -23,8 +24,83 @@ impl User {
         &self.email
     }
 
-    // verifies hashed password
+    // verifies hashed password (Argon2id, or bcrypt for older accounts)
     pub fn verify_password(&self, password: &str) -> bool {
-        verify(&password, &self.password).unwrap_or(false)
+        password::verify_password(password, &self.password)
+    }
+
+    // true when the stored hash predates the current algorithm or parameters
+    pub fn needs_rehash(&self) -> bool {
+        password::needs_rehash(&self.password)
+    }
+
+    // public view of the user, drops the password hash
+    pub fn into_profile(self, id: String, created_at: Option<i64>, preferences: HashMap<String, String>) -> Profile {
+        Profile {
+            id,
+            forename: self.forename,
+            email: self.email,
+            created_at,
+            preferences,
+        }
     }
-}
\ No newline at end of file
+}
+
+// Public profile structure, the only user type sent to the frontend
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Profile {
+    pub id: String,
+    pub forename: String,
+    pub email: String,
+    pub created_at: Option<i64>, // unknown for accounts created before it was recorded
+    pub preferences: HashMap<String, String>,
+}
+
+// Collaborator structure (a member of a shared vault)
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Collaborator {
+    pub user_id: String,
+    pub forename: String,
+    pub email: String,
+    pub role: String,
+}
+
+// Device session structure (one per login)
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct DeviceSession {
+    pub id: String,
+    pub device: String,
+    pub created_at: i64,
+    pub last_seen: i64,
+    pub current: bool,
+}
+
+// Two-factor enrolment structure (shown once as a QR code or typed into an authenticator app)
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct TotpEnrolment {
+    pub secret: String,
+    pub uri: String,
+}
+
+// Login response structure, a session token or a request for the two-factor code
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LoginResponse {
+    Authenticated { token: String },
+    TwoFactorRequired,
+}
+
+// Account deletion report, what was (or in a dry run would be) removed
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct DeletionReport {
+    pub dry_run: bool,
+    pub resumed: bool, // an earlier deletion was interrupted and picked up again
+    pub vaults_deleted: usize,
+    pub vaults_left: usize, // shared vaults another owner keeps
+    pub notebooks: usize,
+    pub notes: usize,
+    pub attachments: usize,
+    pub threads: usize,
+    pub flashcards: usize,
+    pub sessions: usize,
+}
@@ -1,37 +1,106 @@
 use redis::{AsyncCommands, RedisError, ErrorKind, RedisResult};
 use std::collections::HashMap;
-use super::client::conn;
-use super::client::generate_session_token;
+use super::client::{conn, now};
+use super::sessions::{default_device, generate_session_token};
+use super::two_factor::{two_factor_enabled, verify_code};
+use super::throttle::{clear_failures, reserve_attempt};
 use super::models::User;
+use super::attachments::delete_note_attachments;
+use super::threads::delete_scope_threads;
+use super::flashcards::delete_deck;
+use super::embeddings::delete_note_embeddings;
+use super::crypto::{blind_index, decrypt, encrypt, DataKey, UserKeys};
+use super::keys::{create_vault_key, unlock_user_keys};
+use super::access::{open_note, open_notebook, open_vault, require_role, Role};
+use super::error::{DbError, DbResult};
+use super::password::hash_password;
+use super::validation::{normalize_email, validate_email, validate_forename};
+use redis::aio::MultiplexedConnection;
 use uuid::Uuid;
 
 // CREATE USER
-pub async fn create_user(forename: String, email: String, password: String) -> RedisResult<String> {
+pub async fn create_user(forename: String, email: String, password: String) -> DbResult<String> {
+    let forename = validate_forename(&forename)?;
+    let email = normalize_email(&email);
+    validate_email(&email)?;
+
     let mut connection = conn().await?;
 
     let email_key = format!("user:{}", email);
     let id = Uuid::new_v4().to_string();
 
-    // create STRING
-    let _: () = connection.set(&email_key, &id).await?;
-
     let id_key = format!("user:{}", &id);
 
-    // create HASH 
+    // create HASH (first, nothing points at it until the email is claimed)
     let _: () = connection.hset_multiple(
         &id_key, 
-        &[("forename", forename), ("email", email), ("password", password)]
+        &[("forename", forename), ("email", email), ("password", password), ("created_at", now().to_string())]
     ).await?;
 
+    // create STRING (only if the email is free, so an existing account is never replaced)
+    // claimed last, so a failed registration never leaves the email reserved without an account
+    let created: bool = match connection.set_nx(&email_key, &id).await {
+        Ok(created) => created,
+        Err(e) => {
+            let _: () = connection.del(&id_key).await?;
+            return Err(e.into());
+        }
+    };
+    if !created {
+        let _: () = connection.del(&id_key).await?;
+        return Err(DbError::EmailTaken);
+    }
+
     Ok(id_key)
 }
 
-// GET USER
-pub async fn get_user(email: String, password: String) -> RedisResult<String> {
+// GET USER ID (by email)
+pub async fn get_user_id(connection: &mut MultiplexedConnection, email: &str) -> RedisResult<Option<String>> {
+    let normalized = normalize_email(email);
+    let email_key: String = format!("user:{}", normalized);
+
+    // get STRING
+    let user_id: Option<String> = connection.get(&email_key).await?;
+    if user_id.is_some() {
+        return Ok(user_id);
+    }
+
+    // accounts registered before emails were normalized are moved over on first use
+    let legacy_key: String = format!("user:{}", email.trim());
+    if legacy_key == email_key {
+        return Ok(None);
+    }
+
+    let legacy_id: Option<String> = connection.get(&legacy_key).await?;
+    if let Some(id) = &legacy_id {
+        let moved: bool = connection.set_nx(&email_key, id).await?;
+        if moved {
+            let _: () = connection.del(&legacy_key).await?;
+            let _: () = connection.hset(format!("user:{}", id), "email", &normalized).await?;
+        }
+    }
+
+    Ok(legacy_id)
+}
+
+// Outcome of a correct password
+pub enum LoginStep {
+    Authenticated(String, UserKeys), // session token
+    TwoFactor(UserKeys),             // a two-factor code is still needed before a session is created
+}
+
+// GET USER (session token and the unlocked encryption keys)
+pub async fn get_user(email: String, password: String, device: Option<String>) -> DbResult<LoginStep> {
     let mut connection = conn().await?;
 
-    let email_key: String = format!("user:{}", email);
-    let search_key: String = connection.get(email_key).await?;
+    // failures are counted per email and per client device
+    let email = normalize_email(&email);
+    let client = device.clone().unwrap_or_else(default_device);
+    reserve_attempt(&mut connection, &email, &client).await?;
+
+    let Some(search_key) = get_user_id(&mut connection, &email).await? else {
+        return Err(DbError::InvalidCredentials);
+    };
     
     let user_id: String = format!("user:{}", search_key);
     
@@ -49,54 +118,145 @@ pub async fn get_user(email: String, password: String) -> RedisResult<String> {
     let _ = &account.get_email();
 
     if account.verify_password(&password) {
-        let session_user = format!("session:{}", search_key);
-        // get STRING
-        let existing_session:  Option<String> = connection.get(session_user).await?;
-        
-        if let Some(session) = existing_session {
-            let session = format!("session:{}", session);
-            return Ok(session)
+        // older hashes are upgraded while the plain password is at hand
+        if account.needs_rehash() {
+            let _: () = connection.hset(&user_id, "password", hash_password(&password)?).await?;
+        }
+
+        let keys = unlock_user_keys(&mut connection, &search_key, &password).await?;
+
+        // failures keep counting until the second factor is passed too
+        if two_factor_enabled(&mut connection, &search_key).await? {
+            return Ok(LoginStep::TwoFactor(keys));
         }
+        clear_failures(&mut connection, &email, &client).await?;
 
-        // create HASH
-        let session = generate_session_token(connection, search_key).await?;
-        Ok(session)
+        // create HASH (a new session for every login, so each device can be revoked on its own)
+        let session = generate_session_token(connection, search_key, device).await?;
+        Ok(LoginStep::Authenticated(session, keys))
         
     } else {
-        Err(RedisError::from((ErrorKind::TypeError, "Invalid Credentials")))
+        Err(DbError::InvalidCredentials)
+    }
+}
+
+// VERIFY SECOND FACTOR (finishes a login that returned LoginStep::TwoFactor)
+pub async fn verify_second_factor(email: String, device: Option<String>, code: String, keys: &UserKeys) -> DbResult<String> {
+    let mut connection = conn().await?;
+
+    let email = normalize_email(&email);
+    let client = device.clone().unwrap_or_else(default_device);
+    reserve_attempt(&mut connection, &email, &client).await?;
+
+    if !verify_code(&mut connection, &code, keys).await? {
+        return Err(DbError::Invalid("Invalid code".to_string()));
+    }
+    clear_failures(&mut connection, &email, &client).await?;
+
+    // create HASH
+    Ok(generate_session_token(connection, keys.user_id.clone(), device).await?)
+}
+
+// looks up an ID by its blind index, moving pre-encryption plaintext name keys over on first use
+async fn lookup_id(connection: &mut MultiplexedConnection, prefix: &str, index: &str, name: &str, parent: (&str, &str)) -> RedisResult<String> {
+    let index_key: String = format!("{}:{}", prefix, index);
+
+    // get STRING
+    let id: Option<String> = connection.get(&index_key).await?;
+    if let Some(id) = id {
+        return Ok(id);
+    }
+
+    // get STRING (legacy name key)
+    let legacy_key: String = format!("{}:{}", prefix, name);
+    let id: String = connection.get(&legacy_key).await?;
+
+    // legacy name keys were global, only move the ones under the same parent
+    let (field, parent_id) = parent;
+    let owner: Option<String> = connection.hget(format!("{}:{}", prefix, id), field).await?;
+    if owner.as_deref() != Some(parent_id) {
+        return Err(RedisError::from((ErrorKind::TypeError, "Not Found")));
     }
+
+    let _: () = connection.set(&index_key, &id).await?;
+    let _: () = connection.del(&legacy_key).await?;
+
+    Ok(id)
+}
+
+// claims a name key for one of the user's vaults, returns the name it is listed under
+// a name already taken by another of their vaults (e.g. one shared with them) gets a number, "Biology (2)"
+async fn claim_vault_name(connection: &mut MultiplexedConnection, keys: &UserKeys, vault_id: &str, name: &str) -> RedisResult<String> {
+    let mut attempt: usize = 1;
+
+    loop {
+        let listed = match attempt {
+            1 => name.to_string(),
+            n => format!("{} ({})", name, n),
+        };
+        let name_key = format!("vault:{}", blind_index(&keys.data_key, &listed));
+
+        // create STRING (unless it's there), then check whose it is
+        let created: bool = connection.set_nx(&name_key, vault_id).await?;
+        if created {
+            let _: () = connection.hset(format!("vault_index:{}", vault_id), &keys.user_id, &name_key).await?;
+            return Ok(listed);
+        }
+
+        let owner: Option<String> = connection.get(&name_key).await?;
+        if owner.as_deref() == Some(vault_id) {
+            return Ok(listed);
+        }
+
+        attempt += 1;
+    }
+}
+
+// deletes a name key, unless it has since been claimed by another vault
+pub async fn release_name_key(connection: &mut MultiplexedConnection, name_key: &str, id: &str) -> RedisResult<()> {
+    // get STRING
+    let owner: Option<String> = connection.get(name_key).await?;
+    if owner.as_deref() == Some(id) {
+        let _: () = connection.del(name_key).await?;
+    }
+
+    Ok(())
 }
 
 // CREATE VAULT
-pub async fn create_vault(name: String, user_id: String) -> RedisResult<String> {
+pub async fn create_vault(name: String, keys: &UserKeys) -> DbResult<String> {
     let mut connection = conn().await?;
     
+    let user_id: String = keys.user_id.clone();
     let id: String = Uuid::new_v4().to_string();
     let vault_key: String = format!("vault:{}", id);
     let vault_set_key: String = format!("vault:{}", user_id);
 
-    let vault_name_key: String = format!("vault:{}", name);
+    let content_key = create_vault_key(&mut connection, keys, &id).await?;
     
     // create STRING
-    let _: () = connection.set(vault_name_key, &id).await?;
+    claim_vault_name(&mut connection, keys, &id, &name).await?;
 
     // create HASH
     let _: () = connection.hset_multiple(
         &vault_key, 
-        &[("name", name), ("user_id", user_id)]
+        &[("name", encrypt(&content_key, &name)?), ("user_id", user_id.clone())]
     ).await?;
 
     // create SET
     let _: () = connection.sadd(&vault_set_key, &id).await?;
 
+    // create HASH (members)
+    let _: () = connection.hset(format!("members:{}", id), &user_id, Role::Owner.as_str()).await?;
+
     Ok(vault_key)
 }
 
 // GET VAULTS
-pub async fn get_vaults(user_id: String) -> RedisResult<Vec<String>> {
+pub async fn get_vaults(keys: &UserKeys) -> DbResult<Vec<String>> {
     let mut connection = conn().await?;
     
-    let vault_set_key: String = format!("vault:{}", user_id);
+    let vault_set_key: String = format!("vault:{}", keys.user_id);
     
     // get SET
     let vault_keys: Vec<String> = connection.smembers(&vault_set_key).await?;
@@ -104,54 +264,89 @@ pub async fn get_vaults(user_id: String) -> RedisResult<Vec<String>> {
     let mut vault_names: Vec<String> = Vec::new();
     // extract id's
     for id in vault_keys {
+        // vaults whose key was lost in a password reset stay hidden until another owner shares them again
+        // (the ones nobody could share again were deleted with the reset)
+        let content_key = match open_vault(&mut connection, keys, &id, Role::Viewer).await {
+            Err(DbError::KeyLost) => continue,
+            content_key => content_key?,
+        };
         let id_key = format!("vault:{}", id);
-        let name = connection.hget(id_key, "name").await?;
-        vault_names.push(name);
+        let name: String = connection.hget(id_key, "name").await?;
+        let name = decrypt(&content_key, &name)?;
+
+        // shared vaults get a name key for this user the first time they're listed
+        vault_names.push(claim_vault_name(&mut connection, keys, &id, &name).await?);
     }
 
     Ok(vault_names)
 }
 
 // GET VAULT ID
-pub async fn get_vault_id(name: String) -> RedisResult<String> {
+pub async fn get_vault_id(name: String, keys: &UserKeys) -> DbResult<String> {
     let mut connection = conn().await?;
     
-    let vault_name_key: String = format!("vault:{}", name);
+    let index = blind_index(&keys.data_key, &name);
     // get STRING
-    let vault_id: String = connection.get(&vault_name_key).await?;
+    let vault_id: String = lookup_id(&mut connection, "vault", &index, &name, ("user_id", &keys.user_id)).await?;
+    require_role(&mut connection, &keys.user_id, &vault_id, Role::Viewer).await?;
 
     Ok(vault_id)
 }
 
 
 // DELETE VAULT
-pub async fn delete_vault(vault_id: String, vault_name: String, user_id: String) -> RedisResult<()> {
+pub async fn delete_vault(vault_id: String, keys: &UserKeys) -> DbResult<()> {
     let mut connection = conn().await?;
+
+    let content_key = open_vault(&mut connection, keys, &vault_id, Role::Owner).await?;
     
-    // delete HASH
+    // name is read back rather than trusted from the caller
     let hash_key = format!("vault:{}", &vault_id);
+    let vault_name: String = connection.hget(&hash_key, "name").await?;
+    let vault_name = decrypt(&content_key, &vault_name)?;
+
+    // delete HASH
     let _: () = connection.del(&hash_key).await?;
 
     // delete STRING
-    let string_key = format!("vault:{}", &vault_name);
-    let _: () = connection.del(string_key).await?;
+    let string_key = format!("vault:{}", blind_index(&keys.data_key, &vault_name));
+    release_name_key(&mut connection, &string_key, &vault_id).await?;
 
     // delete SET
-    let set_key = format!("vault:{}", &user_id);
+    let set_key = format!("vault:{}", &keys.user_id);
     let _: () = connection.srem(set_key,&vault_id).await?;
 
+    // delete HASH (vault keys)
+    let keys_key = format!("vault_keys:{}", &vault_id);
+    let _: () = connection.del(keys_key).await?;
+
+    // remove the vault from every collaborator
+    let members: Vec<String> = connection.hkeys(format!("members:{}", &vault_id)).await?;
+    for member in members {
+        let _: () = connection.srem(format!("vault:{}", member), &vault_id).await?;
+    }
+    let name_keys: Vec<String> = connection.hvals(format!("vault_index:{}", &vault_id)).await?;
+    for name_key in name_keys {
+        release_name_key(&mut connection, &name_key, &vault_id).await?;
+    }
+
+    // delete HASH (members)
+    let _: () = connection.del(format!("members:{}", &vault_id)).await?;
+    let _: () = connection.del(format!("vault_index:{}", &vault_id)).await?;
+
     Ok(())
 }
 
 // CREATE NOTEBOOK
-pub async fn create_notebook(name: String, vault_id: String) -> RedisResult<String> {
+pub async fn create_notebook(name: String, vault_id: String, keys: &UserKeys) -> DbResult<String> {
     let mut connection = conn().await?;
     
     let id: String = Uuid::new_v4().to_string();
     let notebook_id: String = format!("notebook:{}", id);
     let notebook_set_key: String = format!("notebook:{}", vault_id);
 
-    let notebook_name_key: String = format!("notebook:{}", name);
+    let content_key = open_vault(&mut connection, keys, &vault_id, Role::Editor).await?;
+    let notebook_name_key: String = format!("notebook:{}", blind_index(&content_key, &name));
 
     // create STRING
     let _: () = connection.set(&notebook_name_key, &id).await?;
@@ -159,7 +354,7 @@ pub async fn create_notebook(name: String, vault_id: String) -> RedisResult<Stri
     // create HASH
     let _: () = connection.hset_multiple(
         &notebook_id, 
-        &[("name", name), ("vault_id", vault_id)]
+        &[("name", encrypt(&content_key, &name)?), ("vault_id", vault_id)]
     ).await?;
 
     // create SET
@@ -169,10 +364,11 @@ pub async fn create_notebook(name: String, vault_id: String) -> RedisResult<Stri
 }
 
 // GET NOTEBOOKS
-pub async fn get_notebooks(vault_id: String) -> RedisResult<Vec<String>> {
+pub async fn get_notebooks(vault_id: String, keys: &UserKeys) -> DbResult<Vec<String>> {
     let mut connection = conn().await?;
     
     let notebook_set_key: String = format!("notebook:{}", vault_id);
+    let content_key = open_vault(&mut connection, keys, &vault_id, Role::Viewer).await?;
 
     // get SET
     let notebook_keys: Vec<String> = connection.smembers(&notebook_set_key).await?;
@@ -181,53 +377,70 @@ pub async fn get_notebooks(vault_id: String) -> RedisResult<Vec<String>> {
     // extract ids
     for id in notebook_keys {
         let id_key = format!("notebook:{}", id);
-        let name = connection.hget(id_key, "name").await?;
-        notebook_names.push(name);
+        let name: String = connection.hget(id_key, "name").await?;
+        notebook_names.push(decrypt(&content_key, &name)?);
     }
 
     Ok(notebook_names)
 }
 
 // GET NOTEBOOK ID
-pub async fn get_notebook_id(name: String) -> RedisResult<String> {
+pub async fn get_notebook_id(name: String, vault_id: String, keys: &UserKeys) -> DbResult<String> {
     let mut connection = conn().await?;
     
-    let notebook_name_key: String = format!("notebook:{}", name);
+    let content_key = open_vault(&mut connection, keys, &vault_id, Role::Viewer).await?;
+    let index = blind_index(&content_key, &name);
 
     // get STRING
-    let notebook_id: String = connection.get(&notebook_name_key).await?;
+    let notebook_id: String = lookup_id(&mut connection, "notebook", &index, &name, ("vault_id", &vault_id)).await?;
 
     Ok(notebook_id)
 }
 
 // DELETE NOTEBOOK
-pub async fn delete_notebook(notebook_id: String, notebook_name: String, vault_id: String) -> RedisResult<()> {
+pub async fn delete_notebook(notebook_id: String, keys: &UserKeys) -> DbResult<()> {
     let mut connection = conn().await?;
 
-    // delete HASH
+    // the vault is taken from the notebook itself, never from the caller
+    let (vault_id, content_key) = open_notebook(&mut connection, keys, &notebook_id, Role::Editor).await?;
+
     let hash_key = format!("notebook:{}", &notebook_id);
+    let notebook_name: String = connection.hget(&hash_key, "name").await?;
+    let notebook_name = decrypt(&content_key, &notebook_name)?;
+
+    // delete HASH
     let _: () = connection.del(hash_key).await?;
 
     // delete STRING
-    let string_key = format!("notebook:{}", &notebook_name);
+    let string_key = format!("notebook:{}", blind_index(&content_key, &notebook_name));
     let _: () = connection.del(string_key).await?;
     
     // delete SET
     let set_key = format!("notebook:{}", &vault_id);
     let _: () = connection.srem(set_key,&notebook_id).await?;
 
+    // delete threads + flashcards
+    delete_scope_threads(&mut connection, &notebook_id).await?;
+    delete_deck(&mut connection, &notebook_id).await?;
+
     Ok(())
 }
  
+// note names are indexed per notebook, so equal names in different notebooks never collide
+pub fn note_index(content_key: &DataKey, notebook_id: &str, name: &str) -> String {
+    blind_index(content_key, &format!("{}/{}", notebook_id, name))
+}
+
 // CREATE NOTE
-pub async fn create_note(name: String, notebook_id: String) -> RedisResult<String> {
+pub async fn create_note(name: String, notebook_id: String, keys: &UserKeys) -> DbResult<String> {
     let mut connection = conn().await?;
     
     let id: String = Uuid::new_v4().to_string();
     let note_key: String = format!("note:{}", id);
     let note_set_key: String = format!("note:{}", notebook_id);
 
-    let note_name_key: String = format!("note:{}", name);
+    let (_, content_key) = open_notebook(&mut connection, keys, &notebook_id, Role::Editor).await?;
+    let note_name_key: String = format!("note:{}", note_index(&content_key, &notebook_id, &name));
 
     // create STRING
     let _: () = connection.set(note_name_key, &id).await?;
@@ -235,7 +448,7 @@ pub async fn create_note(name: String, notebook_id: String) -> RedisResult<Strin
     // create HASH
     let _: () = connection.hset_multiple(
         &note_key, 
-        &[("name", name), ("notebook_id", notebook_id), ("content", "".to_string())]
+        &[("name", encrypt(&content_key, &name)?), ("notebook_id", notebook_id), ("content", encrypt(&content_key, "")?)]
     ).await?;
 
     // create SET
@@ -245,10 +458,11 @@ pub async fn create_note(name: String, notebook_id: String) -> RedisResult<Strin
 }
 
 // GET NOTE
-pub async fn get_notes(notebook_id: String) -> RedisResult<Vec<String>> {
+pub async fn get_notes(notebook_id: String, keys: &UserKeys) -> DbResult<Vec<String>> {
     let mut connection = conn().await?;
     
     let notebook_set_key: String = format!("note:{}", notebook_id);
+    let (_, content_key) = open_notebook(&mut connection, keys, &notebook_id, Role::Viewer).await?;
 
     // get SET
     let notebook_keys: Vec<String> = connection.smembers(&notebook_set_key).await?;
@@ -257,63 +471,166 @@ pub async fn get_notes(notebook_id: String) -> RedisResult<Vec<String>> {
     // extract ids
     for id in notebook_keys {
         let id_key = format!("note:{}", id);
-        let name = connection.hget(id_key, "name").await?;
-        notebook_names.push(name);
+        let name: String = connection.hget(id_key, "name").await?;
+        notebook_names.push(decrypt(&content_key, &name)?);
     }
 
     Ok(notebook_names)
 }
 
 // GET NOTE ID
-pub async fn get_note_id(name: String) -> RedisResult<String> {
+pub async fn get_note_id(name: String, notebook_id: String, keys: &UserKeys) -> DbResult<String> {
     let mut connection = conn().await?;
     
-    let notebook_name_key: String = format!("note:{}", name);
+    let (_, content_key) = open_notebook(&mut connection, keys, &notebook_id, Role::Viewer).await?;
+    let index = note_index(&content_key, &notebook_id, &name);
+
     // get STRING
-    let notebook_id: String = connection.get(&notebook_name_key).await?;
+    let note_id: String = lookup_id(&mut connection, "note", &index, &name, ("notebook_id", &notebook_id)).await?;
 
-    Ok(notebook_id)
+    Ok(note_id)
 }
 
 // GET NOTE contents
-pub async fn read_note(id: String) -> RedisResult<String> {
+pub async fn read_note(id: String, keys: &UserKeys) -> DbResult<String> {
     let mut connection = conn().await?;
     
     let notebook_name_key: String = format!("note:{}", id);
+    let (_, content_key) = open_note(&mut connection, keys, &id, Role::Viewer).await?;
     
     // get HASH
     let content: String = connection.hget(&notebook_name_key, "content").await?;
 
-    Ok(content)
+    Ok(decrypt(&content_key, &content)?)
 }
 
 // WRITE TO NOTE
-pub async fn save_note(id: String, content: String) -> RedisResult<String> {
+pub async fn save_note(id: String, content: String, keys: &UserKeys) -> DbResult<String> {
     let mut connection = conn().await?;
     
     let notebook_name_key: String = format!("note:{}", id);
+    let (_, content_key) = open_note(&mut connection, keys, &id, Role::Editor).await?;
     
     // set HASH
-    let content: String = connection.hset(&notebook_name_key, "content", content).await?;
+    let content: String = connection.hset(&notebook_name_key, "content", encrypt(&content_key, &content)?).await?;
 
     Ok(content)
 }
 
 // DELETE NOTE
-pub async fn delete_note(note_id: String, note_name: String, notebook_id: String) -> RedisResult<()> {
+pub async fn delete_note(note_id: String, keys: &UserKeys) -> DbResult<()> {
     let mut connection = conn().await?;
+
+    // the notebook is taken from the note itself, never from the caller
+    let (notebook_id, content_key) = open_note(&mut connection, keys, &note_id, Role::Editor).await?;
+
+    let hash_key = format!("note:{}", &note_id);
+    let note_name: String = connection.hget(&hash_key, "name").await?;
+    let note_name = decrypt(&content_key, &note_name)?;
     
     // delete HASH
-    let hash_key = format!("note:{}", &note_id);
     let _: () = connection.del(hash_key).await?;
 
     // delete STRING
-    let string_key = format!("note:{}", &note_name);
+    let string_key = format!("note:{}", note_index(&content_key, &notebook_id, &note_name));
     let _: () = connection.del(string_key).await?;
 
     // delete SET    
     let set_key = format!("note:{}", &notebook_id);
     let _: () = connection.srem(set_key,&note_id).await?;
 
+    // delete attachments + threads + embeddings
+    delete_note_attachments(&note_id).await?;
+    delete_scope_threads(&mut connection, &note_id).await?;
+    delete_note_embeddings(&mut connection, &note_id).await?;
+
     Ok(())
 }
+
+
+// GET NOTEBOOK CONTENTS (note names and content, used for exports)
+pub async fn get_notebook_contents(notebook_id: String, keys: &UserKeys) -> DbResult<Vec<(String, String)>> {
+    let mut connection = conn().await?;
+
+    let note_set_key: String = format!("note:{}", notebook_id);
+    let (_, content_key) = open_notebook(&mut connection, keys, &notebook_id, Role::Viewer).await?;
+
+    // get SET
+    let note_keys: Vec<String> = connection.smembers(&note_set_key).await?;
+
+    let mut notes: Vec<(String, String)> = Vec::new();
+    // extract names and content
+    for id in note_keys {
+        let id_key = format!("note:{}", id);
+        let (name, content): (String, String) = connection.hget(id_key, &["name", "content"]).await?;
+        notes.push((decrypt(&content_key, &name)?, decrypt(&content_key, &content)?));
+    }
+    notes.sort();
+
+    Ok(notes)
+}
+
+// GET VAULT CONTENTS (notebook names and their notes, used for exports)
+pub async fn get_vault_contents(vault_id: String, keys: &UserKeys) -> DbResult<Vec<(String, Vec<(String, String)>)>> {
+    let mut connection = conn().await?;
+
+    let notebook_set_key: String = format!("notebook:{}", vault_id);
+    let content_key = open_vault(&mut connection, keys, &vault_id, Role::Viewer).await?;
+
+    // get SET
+    let notebook_keys: Vec<String> = connection.smembers(&notebook_set_key).await?;
+
+    let mut notebooks: Vec<(String, Vec<(String, String)>)> = Vec::new();
+    for id in notebook_keys {
+        let id_key = format!("notebook:{}", id);
+        let name: String = connection.hget(id_key, "name").await?;
+        notebooks.push((decrypt(&content_key, &name)?, get_notebook_contents(id, keys).await?));
+    }
+    notebooks.sort();
+
+    Ok(notebooks)
+}
+
+// note ids, names and content of a notebook the caller has already opened
+async fn notebook_notes(connection: &mut MultiplexedConnection, notebook_id: &str, content_key: &DataKey) -> DbResult<Vec<(String, String, String)>> {
+    // get SET
+    let note_ids: Vec<String> = connection.smembers(format!("note:{}", notebook_id)).await?;
+
+    let mut notes: Vec<(String, String, String)> = Vec::new();
+    for id in note_ids {
+        let (name, content): (String, String) = connection.hget(format!("note:{}", id), &["name", "content"]).await?;
+        notes.push((id, decrypt(content_key, &name)?, decrypt(content_key, &content)?));
+    }
+
+    Ok(notes)
+}
+
+// GET SCOPE NOTES ("note:<id>", "notebook:<id>" or "vault:<id>", used to answer questions about them)
+pub async fn get_scope_notes(scope: String, keys: &UserKeys) -> DbResult<Vec<(String, String, String)>> {
+    let mut connection = conn().await?;
+
+    match scope.split_once(':') {
+        Some(("note", id)) => {
+            let (_, content_key) = open_note(&mut connection, keys, id, Role::Viewer).await?;
+            let (name, content): (String, String) = connection.hget(format!("note:{}", id), &["name", "content"]).await?;
+            Ok(vec![(id.to_string(), decrypt(&content_key, &name)?, decrypt(&content_key, &content)?)])
+        }
+        Some(("notebook", id)) => {
+            let (_, content_key) = open_notebook(&mut connection, keys, id, Role::Viewer).await?;
+            notebook_notes(&mut connection, id, &content_key).await
+        }
+        Some(("vault", id)) => {
+            let content_key = open_vault(&mut connection, keys, id, Role::Viewer).await?;
+
+            // get SET
+            let notebook_ids: Vec<String> = connection.smembers(format!("notebook:{}", id)).await?;
+
+            let mut notes: Vec<(String, String, String)> = Vec::new();
+            for notebook_id in notebook_ids {
+                notes.extend(notebook_notes(&mut connection, &notebook_id, &content_key).await?);
+            }
+            Ok(notes)
+        }
+        _ => Err(DbError::Invalid("Ask about a note, notebook or vault".to_string())),
+    }
+}
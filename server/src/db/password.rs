@@ -0,0 +1,158 @@
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use super::client::env_integer;
+use super::error::{DbError, DbResult};
+
+// Password hash formats, told apart by the stored string
+// new hashes are always Argon2id, bcrypt is only kept for accounts created before it
+#[derive(Debug, PartialEq)]
+enum Scheme {
+    Argon2id,
+    Bcrypt,
+}
+
+// Implementation for scheme methods
+impl Scheme {
+    fn detect(stored: &str) -> Option<Self> {
+        if stored.starts_with("$argon2id$") {
+            Some(Scheme::Argon2id)
+        } else if ["$2a$", "$2b$", "$2x$", "$2y$"].iter().any(|prefix| stored.starts_with(prefix)) {
+            Some(Scheme::Bcrypt)
+        } else {
+            None
+        }
+    }
+}
+
+// Argon2id parameters for new hashes (defaults follow the OWASP recommendation)
+// PASSWORD_MEMORY_KIB, PASSWORD_ITERATIONS and PASSWORD_PARALLELISM override them
+fn params() -> DbResult<Params> {
+    let env_param = |name, default: u32| u32::try_from(env_integer(name, default.into())).unwrap_or(default);
+
+    Params::new(
+        env_param("PASSWORD_MEMORY_KIB", Params::DEFAULT_M_COST),
+        env_param("PASSWORD_ITERATIONS", Params::DEFAULT_T_COST),
+        env_param("PASSWORD_PARALLELISM", Params::DEFAULT_P_COST),
+        None,
+    )
+    .map_err(|e| DbError::Other(format!("Invalid password hashing parameters: {}", e)))
+}
+
+fn hasher() -> DbResult<Argon2<'static>> {
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params()?))
+}
+
+// HASH PASSWORD (Argon2id PHC string, salt and parameters included)
+pub fn hash_password(password: &str) -> DbResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    hasher()?
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| DbError::Other(e.to_string()))
+}
+
+// VERIFY PASSWORD (any supported scheme, parameters are read from the hash itself)
+pub fn verify_password(password: &str, stored: &str) -> bool {
+    match Scheme::detect(stored) {
+        Some(Scheme::Argon2id) => PasswordHash::new(stored)
+            .is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok()),
+        Some(Scheme::Bcrypt) => bcrypt::verify(password, stored).unwrap_or(false),
+        None => false,
+    }
+}
+
+// NEEDS REHASH (older scheme, or Argon2id with other parameters than the configured ones)
+pub fn needs_rehash(stored: &str) -> bool {
+    if Scheme::detect(stored) != Some(Scheme::Argon2id) {
+        return true;
+    }
+
+    let (Ok(hash), Ok(current)) = (PasswordHash::new(stored), params()) else {
+        return true;
+    };
+
+    match Params::try_from(&hash) {
+        Ok(stored) => {
+            hash.version != Some(Version::V0x13.into())
+                || stored.m_cost() != current.m_cost()
+                || stored.t_cost() != current.t_cost()
+                || stored.p_cost() != current.p_cost()
+        }
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Argon2id hash made with other parameters than the configured ones
+    fn argon2id_with(m_cost: u32, t_cost: u32, p_cost: u32) -> String {
+        let params = Params::new(m_cost, t_cost, p_cost, None).unwrap();
+        let salt = SaltString::generate(&mut OsRng);
+
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password(b"correct horse", &salt)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn argon2id_round_trip() {
+        let stored = hash_password("correct horse").unwrap();
+
+        assert!(stored.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse", &stored));
+        assert!(!verify_password("wrong horse", &stored));
+        assert!(!needs_rehash(&stored));
+    }
+
+    #[test]
+    fn bcrypt_hashes_still_verify() {
+        let stored = bcrypt::hash("correct horse", 4).unwrap();
+        assert!(stored.starts_with("$2b$"));
+        assert!(verify_password("correct horse", &stored));
+        assert!(!verify_password("wrong horse", &stored));
+
+        // the other bcrypt prefixes are accepted too
+        let stored = bcrypt::hash_with_result("correct horse", 4).unwrap().format_for_version(bcrypt::Version::TwoY);
+        assert!(stored.starts_with("$2y$"));
+        assert!(verify_password("correct horse", &stored));
+    }
+
+    #[test]
+    fn bcrypt_needs_rehash() {
+        let stored = bcrypt::hash("correct horse", 4).unwrap();
+        assert!(needs_rehash(&stored));
+    }
+
+    #[test]
+    fn other_argon2id_parameters_need_rehash() {
+        let (m_cost, t_cost, p_cost) = (Params::DEFAULT_M_COST, Params::DEFAULT_T_COST, Params::DEFAULT_P_COST);
+
+        for stored in [
+            argon2id_with(m_cost / 2, t_cost, p_cost),
+            argon2id_with(m_cost, t_cost + 1, p_cost),
+            argon2id_with(m_cost, t_cost, p_cost + 1),
+        ] {
+            assert!(verify_password("correct horse", &stored));
+            assert!(needs_rehash(&stored));
+        }
+    }
+
+    #[test]
+    fn unknown_scheme_is_rejected() {
+        // argon2i, plain SHA-256 crypt and a plaintext password
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, Params::default())
+            .hash_password(b"correct horse", &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string();
+
+        for stored in [argon2i.as_str(), "$5$rounds=5000$salt$hash", "correct horse", ""] {
+            assert_eq!(Scheme::detect(stored), None);
+            assert!(!verify_password("correct horse", stored));
+            assert!(needs_rehash(stored));
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use redis::{AsyncCommands, Script};
+use std::collections::HashMap;
+use super::access::{require_role, Role};
+use super::account::check_password;
+use super::client::conn;
+use super::crypto::UserKeys;
+use super::error::{DbError, DbResult};
+use super::validation::{normalize_email, validate_email, validate_forename, validate_preference};
+
+// claims the new email key, releases the old one and updates the user hash in one step
+// KEYS: new email key, old email key, user hash   ARGV: user id, new email
+const MOVE_EMAIL: &str = r"
+if redis.call('SETNX', KEYS[1], ARGV[1]) == 0 then
+    return 0
+end
+if redis.call('GET', KEYS[2]) == ARGV[1] then
+    redis.call('DEL', KEYS[2])
+end
+redis.call('HSET', KEYS[3], 'email', ARGV[2])
+return 1
+";
+
+// UPDATE FORENAME
+pub async fn update_user_forename(forename: String, keys: &UserKeys) -> DbResult<()> {
+    let forename = validate_forename(&forename)?;
+    let mut connection = conn().await?;
+
+    // set HASH
+    let _: () = connection.hset(format!("user:{}", keys.user_id), "forename", forename).await?;
+
+    Ok(())
+}
+
+// CHANGE EMAIL (needs the password, the old address stops working straight away)
+pub async fn change_user_email(email: String, password: String, keys: &UserKeys) -> DbResult<()> {
+    let email = normalize_email(&email);
+    validate_email(&email)?;
+
+    let mut connection = conn().await?;
+    let user_key = format!("user:{}", keys.user_id);
+
+    check_password(&mut connection, &keys.user_id, &password).await?;
+
+    // get HASH
+    let current: String = connection.hget(&user_key, "email").await?;
+    if current == email {
+        return Ok(());
+    }
+
+    // move STRING (email -> id) and set HASH
+    let moved: bool = Script::new(MOVE_EMAIL)
+        .key(format!("user:{}", email))
+        .key(format!("user:{}", current))
+        .key(&user_key)
+        .arg(&keys.user_id)
+        .arg(&email)
+        .invoke_async(&mut connection)
+        .await?;
+
+    if !moved {
+        return Err(DbError::EmailTaken);
+    }
+
+    Ok(())
+}
+
+// GET PREFERENCES
+pub async fn get_user_preferences(keys: &UserKeys) -> DbResult<HashMap<String, String>> {
+    let mut connection = conn().await?;
+
+    // get HASH
+    Ok(connection.hgetall(format!("preferences:{}", keys.user_id)).await?)
+}
+
+// UPDATE PREFERENCES (an empty value clears that preference, returns the full map)
+pub async fn update_user_preferences(preferences: HashMap<String, String>, keys: &UserKeys) -> DbResult<HashMap<String, String>> {
+    let mut connection = conn().await?;
+    let preferences_key = format!("preferences:{}", keys.user_id);
+
+    let mut set: Vec<(String, String)> = Vec::new();
+    let mut cleared: Vec<String> = Vec::new();
+
+    // everything is checked before anything is written
+    for (name, value) in preferences {
+        match validate_preference(&name, &value)? {
+            None => cleared.push(name),
+            Some(value) => {
+                if name == "default_vault" {
+                    require_role(&mut connection, &keys.user_id, &value, Role::Viewer).await?;
+                }
+                set.push((name, value));
+            }
+        }
+    }
+
+    // set + delete HASH fields
+    if !set.is_empty() {
+        let _: () = connection.hset_multiple(&preferences_key, &set).await?;
+    }
+    if !cleared.is_empty() {
+        let _: () = connection.hdel(&preferences_key, &cleared).await?;
+    }
+
+    // get HASH
+    Ok(connection.hgetall(&preferences_key).await?)
+}
@@ -0,0 +1,236 @@
+use redis::{AsyncCommands, Pipeline, RedisResult};
+use redis::aio::MultiplexedConnection;
+use std::collections::HashMap;
+use super::crypto::{
+    blind_index, decrypt, decrypt_bytes, encrypt, encrypt_bytes, generate_key, seal_key, wrap_key, DataKey, UserKeys,
+};
+use super::embeddings::embedding_keys;
+use super::error::DbResult;
+use super::keys::LOST_KEY;
+use super::ops::note_index;
+
+// Re-encrypts a vault under a fresh key, so a removed member's copy of the old one opens nothing
+// everything is read first and every write (the new wrapped keys included) goes out in one MULTI/EXEC,
+// so members never see content under one key and the vault key under the other;
+// every key is WATCHed before it's read, a save in between aborts the EXEC and the rotation starts over
+struct Rotation<'a> {
+    connection: &'a mut MultiplexedConnection,
+    old: &'a DataKey,
+    new: DataKey,
+    pipe: Pipeline,
+}
+
+// Implementation for rotation methods
+impl Rotation<'_> {
+    // keys read for the rotation, the transaction only applies if none of them changed
+    async fn watch(&mut self, key: &str) -> RedisResult<()> {
+        redis::cmd("WATCH").arg(key).query_async(self.connection).await
+    }
+
+    fn reencrypt(&self, value: &str) -> RedisResult<String> {
+        encrypt(&self.new, &decrypt(self.old, value)?)
+    }
+
+    // re-encrypts the given fields of a hash, missing ones are skipped
+    async fn fields(&mut self, hash_key: &str, fields: &[&str]) -> RedisResult<()> {
+        self.watch(hash_key).await?;
+
+        // get HASH fields
+        let values: Vec<Option<String>> = redis::cmd("HMGET").arg(hash_key).arg(fields).query_async(self.connection).await?;
+
+        for (field, value) in fields.iter().zip(values) {
+            if let Some(value) = value {
+                let value = self.reencrypt(&value)?;
+                self.pipe.hset(hash_key, *field, value).ignore();
+            }
+        }
+        Ok(())
+    }
+
+    // moves a name key to its blind index under the new key (legacy plaintext name keys are left alone)
+    async fn name_key(&mut self, prefix: &str, old_index: String, new_index: String, id: &str) -> RedisResult<()> {
+        let old_key = format!("{}:{}", prefix, old_index);
+        self.watch(&old_key).await?;
+
+        // get STRING
+        let current: Option<String> = self.connection.get(&old_key).await?;
+        if current.as_deref() == Some(id) {
+            self.pipe.del(&old_key).ignore();
+            self.pipe.set(format!("{}:{}", prefix, new_index), id).ignore();
+        }
+        Ok(())
+    }
+
+    // threads of a note or notebook (title, system prompt and every turn)
+    async fn threads(&mut self, scope_id: &str) -> RedisResult<()> {
+        let threads_key = format!("thread:{}", scope_id);
+        self.watch(&threads_key).await?;
+
+        // get SET
+        let thread_ids: Vec<String> = self.connection.smembers(&threads_key).await?;
+
+        for id in thread_ids {
+            self.fields(&format!("thread:{}", id), &["title", "system"]).await?;
+
+            // get LIST, then replace it whole
+            let messages_key = format!("messages:{}", id);
+            self.watch(&messages_key).await?;
+            let messages: Vec<String> = self.connection.lrange(&messages_key, 0, -1).await?;
+            if messages.is_empty() {
+                continue;
+            }
+
+            let mut turns: Vec<String> = Vec::new();
+            for message in messages {
+                turns.push(self.reencrypt(&message)?);
+            }
+            self.pipe.del(&messages_key).ignore();
+            self.pipe.rpush(&messages_key, turns).ignore();
+        }
+        Ok(())
+    }
+
+    // name, content and name key of a note, its attachments and threads
+    async fn note(&mut self, note_id: &str, notebook_id: &str) -> RedisResult<()> {
+        let hash_key = format!("note:{}", note_id);
+        self.watch(&hash_key).await?;
+
+        // get HASH
+        let name: Option<String> = self.connection.hget(&hash_key, "name").await?;
+        if let Some(name) = name {
+            let name = decrypt(self.old, &name)?;
+            let (old_index, new_index) = (note_index(self.old, notebook_id, &name), note_index(&self.new, notebook_id, &name));
+            self.name_key("note", old_index, new_index, note_id).await?;
+        }
+        self.fields(&hash_key, &["name", "content"]).await?;
+
+        let attachments_key = format!("attachment:{}", note_id);
+        self.watch(&attachments_key).await?;
+
+        // get SET
+        let attachment_ids: Vec<String> = self.connection.smembers(&attachments_key).await?;
+        for id in attachment_ids {
+            let attachment_key = format!("attachment:{}", id);
+            self.fields(&attachment_key, &["name"]).await?;
+
+            // get STRING chunks
+            let chunks: usize = self.connection.hget(&attachment_key, "chunks").await.unwrap_or(0);
+            for chunk in 0..chunks {
+                let chunk_key = format!("attachment:{}:{}", id, chunk);
+                self.watch(&chunk_key).await?;
+                let bytes: Vec<u8> = self.connection.get(&chunk_key).await?;
+                let bytes = encrypt_bytes(&self.new, &decrypt_bytes(self.old, &bytes)?)?;
+                self.pipe.set(chunk_key, bytes).ignore();
+            }
+        }
+
+        self.threads(note_id).await?;
+
+        // delete HASH + SET (the search index is keyed under the old key, it's rebuilt on the next save or search)
+        let index_keys = embedding_keys(self.connection, note_id).await?;
+        self.pipe.del(index_keys).ignore();
+
+        Ok(())
+    }
+
+    // name and name key of a notebook, its notes, threads and flashcards
+    async fn notebook(&mut self, notebook_id: &str) -> RedisResult<()> {
+        let hash_key = format!("notebook:{}", notebook_id);
+        self.watch(&hash_key).await?;
+
+        // get HASH
+        let name: Option<String> = self.connection.hget(&hash_key, "name").await?;
+        if let Some(name) = name {
+            let name = decrypt(self.old, &name)?;
+            let (old_index, new_index) = (blind_index(self.old, &name), blind_index(&self.new, &name));
+            self.name_key("notebook", old_index, new_index, notebook_id).await?;
+        }
+        self.fields(&hash_key, &["name"]).await?;
+
+        let notes_key = format!("note:{}", notebook_id);
+        self.watch(&notes_key).await?;
+
+        // get SET
+        let note_ids: Vec<String> = self.connection.smembers(&notes_key).await?;
+        for note_id in note_ids {
+            self.note(&note_id, notebook_id).await?;
+        }
+
+        self.threads(notebook_id).await?;
+
+        // get HASH (flashcards)
+        let deck_key = format!("deck:{}", notebook_id);
+        self.watch(&deck_key).await?;
+        let cards: HashMap<String, String> = self.connection.hgetall(&deck_key).await?;
+        for (card_id, card) in cards {
+            let card = self.reencrypt(&card)?;
+            self.pipe.hset(&deck_key, card_id, card).ignore();
+        }
+
+        Ok(())
+    }
+}
+
+// ROTATE VAULT KEY
+// `old` is the vault's current key, every remaining member gets the new one (sealed to their public key),
+// the caller gets it wrapped under their own data key, members whose key was lost stay that way
+pub async fn rotate_vault_key(connection: &mut MultiplexedConnection, keys: &UserKeys, vault_id: &str, old: &DataKey) -> DbResult<()> {
+    loop {
+        if try_rotation(connection, keys, vault_id, old).await? {
+            return Ok(());
+        }
+    }
+}
+
+// one attempt, false when a watched key changed before EXEC
+async fn try_rotation(connection: &mut MultiplexedConnection, keys: &UserKeys, vault_id: &str, old: &DataKey) -> DbResult<bool> {
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+
+    let mut rotation = Rotation { connection, old, new: generate_key(), pipe };
+
+    rotation.fields(&format!("vault:{}", vault_id), &["name"]).await?;
+
+    let notebooks_key = format!("notebook:{}", vault_id);
+    rotation.watch(&notebooks_key).await?;
+
+    // get SET
+    let notebook_ids: Vec<String> = rotation.connection.smembers(&notebooks_key).await?;
+    for notebook_id in notebook_ids {
+        rotation.notebook(&notebook_id).await?;
+    }
+
+    // get HASH (remaining members and the keys they hold)
+    let keys_key = format!("vault_keys:{}", vault_id);
+    let members_key = format!("members:{}", vault_id);
+    rotation.watch(&keys_key).await?;
+    rotation.watch(&members_key).await?;
+    let members: Vec<String> = rotation.connection.hkeys(&members_key).await?;
+    let held: HashMap<String, String> = rotation.connection.hgetall(&keys_key).await?;
+
+    let mut wrapped: Vec<(String, String)> = Vec::new();
+    for member in members {
+        let key = if member == keys.user_id {
+            wrap_key(&keys.data_key, &rotation.new)?
+        } else if held.get(&member).map(String::as_str) == Some(LOST_KEY) {
+            LOST_KEY.to_string()
+        } else {
+            let public_key: Option<String> = rotation.connection.hget(format!("user:{}", member), "public_key").await?;
+            match public_key {
+                Some(public_key) => seal_key(&public_key, &rotation.new)?,
+                None => LOST_KEY.to_string(),
+            }
+        };
+        wrapped.push((member, key));
+    }
+
+    // replace HASH (vault keys), then apply everything at once (nil when a watched key changed)
+    let Rotation { connection, mut pipe, .. } = rotation;
+    pipe.del(&keys_key).ignore();
+    if !wrapped.is_empty() {
+        pipe.hset_multiple(&keys_key, &wrapped).ignore();
+    }
+    let applied: Option<()> = pipe.query_async(connection).await?;
+
+    Ok(applied.is_some())
+}
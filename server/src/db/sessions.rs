@@ -0,0 +1,252 @@
+use redis::{AsyncCommands, RedisResult};
+use redis::aio::MultiplexedConnection;
+use std::collections::HashMap;
+use uuid::Uuid;
+use super::client::{conn, env_seconds, now};
+use super::error::{DbError, DbResult};
+use super::models::DeviceSession;
+
+// longest device label kept
+const MAX_DEVICE_LABEL: usize = 64;
+
+// idle timeout, renewed whenever the session is used (default 8 hours)
+fn session_ttl() -> i64 {
+    env_seconds("SESSION_TTL", 28800)
+}
+
+// absolute lifetime, a session is never renewed past it (default 7 days)
+fn session_max_age() -> i64 {
+    env_seconds("SESSION_MAX_AGE", 604800)
+}
+
+// seconds a session started at `created_at` may live from now
+fn remaining(created_at: i64) -> i64 {
+    (created_at + session_max_age() - now()).min(session_ttl())
+}
+
+// label used when the frontend doesn't send one, e.g. "linux (desk-pc)"
+pub fn default_device() -> String {
+    match std::env::var("HOSTNAME").or_else(|_| std::env::var("COMPUTERNAME")) {
+        Ok(host) => format!("{} ({})", std::env::consts::OS, host),
+        Err(_) => std::env::consts::OS.to_string(),
+    }
+}
+
+// session tokens are handed out as "session:<uuid>", anything else is rejected
+// so a token can never be used to read or delete an unrelated key
+fn is_session_token(token: &str) -> bool {
+    token
+        .strip_prefix("session:")
+        .is_some_and(|id| Uuid::parse_str(id).is_ok())
+}
+
+// stores a token for a session, id, device and `created_at` are kept across token rotations
+async fn store_token(connection: &mut MultiplexedConnection, user_id: &str, session_id: &str, device: &str, created_at: i64) -> RedisResult<String> {
+    let token_key = format!("session:{}", Uuid::new_v4());
+    let ttl = remaining(created_at);
+
+    // create HASH
+    let _: () = connection.hset_multiple(
+        &token_key,
+        &[
+            ("user_id", user_id.to_string()),
+            ("id", session_id.to_string()),
+            ("device", device.to_string()),
+            ("created_at", created_at.to_string()),
+            ("last_seen", now().to_string()),
+        ]
+    ).await?;
+    // set EXPIRY
+    let _: () = connection.expire(&token_key, ttl).await?;
+
+    // set HASH (the user's sessions, id -> token)
+    let _: () = connection.hset(format!("sessions:{}", user_id), session_id, &token_key).await?;
+
+    Ok(token_key)
+}
+
+// CREATE SESSION (one per login, so each device gets its own)
+pub async fn generate_session_token(mut connection: MultiplexedConnection, user_id: String, device: Option<String>) -> RedisResult<String> {
+    let device: String = device
+        .map(|device| device.trim().chars().take(MAX_DEVICE_LABEL).collect::<String>())
+        .filter(|device| !device.is_empty())
+        .unwrap_or_else(default_device);
+
+    // sessions used to be a single token per user
+    let _: () = connection.del(format!("session:{}", user_id)).await?;
+
+    let session_id = Uuid::new_v4().to_string();
+    store_token(&mut connection, &user_id, &session_id, &device, now()).await
+}
+
+// resolves the user a session token belongs to, sliding its expiry forward
+pub async fn session_user(connection: &mut MultiplexedConnection, token: &str) -> DbResult<String> {
+    if !is_session_token(token) {
+        return Err(DbError::Unauthorized);
+    }
+
+    // get HASH
+    let (user_id, session_id, created_at): (Option<String>, Option<String>, Option<i64>) =
+        connection.hget(token, &["user_id", "id", "created_at"]).await?;
+    let user_id = user_id.ok_or(DbError::SessionExpired)?;
+
+    // sessions from before the absolute lifetime start counting now
+    let created_at = match created_at {
+        Some(created_at) => created_at,
+        None => {
+            let created_at = now();
+            let _: () = connection.hset(token, "created_at", created_at).await?;
+            created_at
+        }
+    };
+
+    // sessions from before device tracking are listed from now on
+    if session_id.is_none() {
+        let session_id = Uuid::new_v4().to_string();
+        let _: () = connection.hset_multiple(token, &[("id", session_id.clone()), ("device", default_device())]).await?;
+        let _: () = connection.hset(format!("sessions:{}", user_id), &session_id, token).await?;
+    }
+
+    let ttl = remaining(created_at);
+    if ttl <= 0 {
+        remove_session(connection, &user_id, token).await?;
+        return Err(DbError::SessionExpired);
+    }
+
+    // set HASH + EXPIRY
+    let _: () = connection.hset(token, "last_seen", now()).await?;
+    let _: () = connection.expire(token, ttl).await?;
+
+    Ok(user_id)
+}
+
+// deletes a session token and its entry in the user's sessions
+async fn remove_session(connection: &mut MultiplexedConnection, user_id: &str, token: &str) -> RedisResult<()> {
+    let session_id: Option<String> = connection.hget(token, "id").await?;
+
+    // delete HASH
+    let _: () = connection.del(token).await?;
+
+    // delete HASH field
+    if let Some(session_id) = session_id {
+        let _: () = connection.hdel(format!("sessions:{}", user_id), session_id).await?;
+    }
+
+    Ok(())
+}
+
+// ROTATE SESSION (new token, same session and absolute expiry)
+pub async fn rotate_session(token: String) -> DbResult<String> {
+    let mut connection = conn().await?;
+
+    let user_id: String = session_user(&mut connection, &token).await?;
+    let (session_id, device, created_at): (String, String, i64) = connection.hget(&token, &["id", "device", "created_at"]).await?;
+
+    let rotated = store_token(&mut connection, &user_id, &session_id, &device, created_at).await?;
+
+    // delete HASH (old token)
+    let _: () = connection.del(&token).await?;
+
+    Ok(rotated)
+}
+
+// DELETE SESSION (logout)
+pub async fn delete_session(token: String) -> DbResult<bool> {
+    let mut connection = conn().await?;
+
+    let user_id: String = session_user(&mut connection, &token).await?;
+    remove_session(&mut connection, &user_id, &token).await?;
+
+    Ok(true)
+}
+
+// GET SESSIONS (every active device of the user, most recently used first)
+pub async fn get_sessions(token: String) -> DbResult<Vec<DeviceSession>> {
+    let mut connection = conn().await?;
+
+    let user_id: String = session_user(&mut connection, &token).await?;
+    let current: String = connection.hget(&token, "id").await?;
+
+    // get HASH
+    let index_key = format!("sessions:{}", user_id);
+    let tokens: HashMap<String, String> = connection.hgetall(&index_key).await?;
+
+    let mut sessions: Vec<DeviceSession> = Vec::new();
+    for (id, session_token) in tokens {
+        let (device, created_at, last_seen): (Option<String>, Option<i64>, Option<i64>) =
+            connection.hget(&session_token, &["device", "created_at", "last_seen"]).await?;
+
+        // expired tokens are dropped from the list
+        let Some(created_at) = created_at else {
+            let _: () = connection.hdel(&index_key, &id).await?;
+            continue;
+        };
+
+        sessions.push(DeviceSession {
+            current: id == current,
+            id,
+            device: device.unwrap_or_default(),
+            created_at,
+            last_seen: last_seen.unwrap_or(created_at),
+        });
+    }
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen));
+
+    Ok(sessions)
+}
+
+// REVOKE SESSION (returns true when it was the caller's own session)
+pub async fn revoke_session(token: String, session_id: String) -> DbResult<bool> {
+    let mut connection = conn().await?;
+
+    let user_id: String = session_user(&mut connection, &token).await?;
+
+    // get HASH
+    let session_token: Option<String> = connection.hget(format!("sessions:{}", user_id), &session_id).await?;
+    let session_token = session_token.ok_or_else(|| DbError::Other("Session not found".to_string()))?;
+
+    // delete HASH + HASH field
+    let _: () = connection.del(&session_token).await?;
+    let _: () = connection.hdel(format!("sessions:{}", user_id), &session_id).await?;
+
+    Ok(session_token == token)
+}
+
+// REVOKE OTHER SESSIONS (returns how many were revoked)
+pub async fn revoke_other_sessions(token: String) -> DbResult<usize> {
+    let mut connection = conn().await?;
+
+    let user_id: String = session_user(&mut connection, &token).await?;
+
+    // get HASH
+    let index_key = format!("sessions:{}", user_id);
+    let tokens: HashMap<String, String> = connection.hgetall(&index_key).await?;
+
+    let mut revoked: usize = 0;
+    for (id, session_token) in tokens.into_iter().filter(|(_, session_token)| *session_token != token) {
+        // delete HASH + HASH field
+        let _: () = connection.del(&session_token).await?;
+        let _: () = connection.hdel(&index_key, &id).await?;
+        revoked += 1;
+    }
+
+    Ok(revoked)
+}
+
+// DELETE USER SESSIONS (every device, e.g. after a password reset)
+pub async fn delete_user_sessions(connection: &mut MultiplexedConnection, user_id: &str) -> RedisResult<()> {
+    let keys = user_session_keys(connection, user_id).await?;
+
+    // delete HASH per token, and the index
+    connection.del(keys).await
+}
+
+// every session of a user and the index listing them
+pub async fn user_session_keys(connection: &mut MultiplexedConnection, user_id: &str) -> RedisResult<Vec<String>> {
+    let index_key = format!("sessions:{}", user_id);
+
+    // get HASH
+    let mut keys: Vec<String> = connection.hvals(&index_key).await?;
+    keys.push(index_key);
+    Ok(keys)
+}
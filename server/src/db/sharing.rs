@@ -0,0 +1,172 @@
+use redis::{AsyncCommands, RedisResult};
+use redis::aio::MultiplexedConnection;
+use std::collections::HashMap;
+use super::access::{open_vault, require_role, vault_role, Role};
+use super::client::conn;
+use super::crypto::{seal_key, UserKeys};
+use super::keys::LOST_KEY;
+use super::error::{DbError, DbResult};
+use super::models::Collaborator;
+use super::ops::{get_user_id, release_name_key};
+use super::flashcards::delete_user_reviews;
+use super::rotation::rotate_vault_key;
+
+fn sharing_error(message: &str) -> DbError {
+    DbError::Other(message.to_string())
+}
+
+// vaults created before sharing have no members hash yet, record the owner first
+async fn ensure_owner_member(connection: &mut MultiplexedConnection, vault_id: &str, user_id: &str) -> DbResult<()> {
+    let _: () = connection.hset_nx(format!("members:{}", vault_id), user_id, Role::Owner.as_str()).await?;
+    Ok(())
+}
+
+// stops the last owner from leaving or being demoted
+async fn check_other_owner(connection: &mut MultiplexedConnection, vault_id: &str, user_id: &str) -> DbResult<()> {
+    let members: HashMap<String, String> = connection.hgetall(format!("members:{}", vault_id)).await?;
+    let other_owner = members
+        .iter()
+        .any(|(member, role)| member != user_id && role == Role::Owner.as_str());
+
+    if other_owner {
+        Ok(())
+    } else {
+        Err(sharing_error("A vault must keep at least one owner"))
+    }
+}
+
+// INVITE COLLABORATOR
+pub async fn invite_collaborator(vault_id: String, email: String, role: String, keys: &UserKeys) -> DbResult<()> {
+    let mut connection = conn().await?;
+
+    let role = Role::parse(&role)?;
+    let vault_key = open_vault(&mut connection, keys, &vault_id, Role::Owner).await?;
+    ensure_owner_member(&mut connection, &vault_id, &keys.user_id).await?;
+
+    // get STRING
+    let invitee_id: Option<String> = get_user_id(&mut connection, &email).await?;
+    let invitee_id = invitee_id.ok_or_else(|| sharing_error("No account is registered with that email"))?;
+
+    // members who lost their key in a password reset can be invited again
+    if vault_role(&mut connection, &invitee_id, &vault_id).await?.is_some() {
+        let held: Option<String> = connection.hget(format!("vault_keys:{}", vault_id), &invitee_id).await?;
+        if held.as_deref() != Some(LOST_KEY) {
+            return Err(sharing_error("That user already has access to this vault"));
+        }
+    }
+
+    // get HASH
+    let public_key: Option<String> = connection.hget(format!("user:{}", invitee_id), "public_key").await?;
+    let public_key = public_key.ok_or_else(|| sharing_error("That user must sign in once before vaults can be shared with them"))?;
+
+    // create HASH (vault key sealed to the invitee)
+    let _: () = connection.hset(format!("vault_keys:{}", vault_id), &invitee_id, seal_key(&public_key, &vault_key)?).await?;
+
+    // create HASH (members)
+    let _: () = connection.hset(format!("members:{}", vault_id), &invitee_id, role.as_str()).await?;
+
+    // create SET
+    let _: () = connection.sadd(format!("vault:{}", invitee_id), &vault_id).await?;
+
+    Ok(())
+}
+
+// GET COLLABORATORS
+pub async fn get_collaborators(vault_id: String, keys: &UserKeys) -> DbResult<Vec<Collaborator>> {
+    let mut connection = conn().await?;
+
+    let role = require_role(&mut connection, &keys.user_id, &vault_id, Role::Viewer).await?;
+    if role == Role::Owner {
+        ensure_owner_member(&mut connection, &vault_id, &keys.user_id).await?;
+    }
+
+    // get HASH
+    let members: HashMap<String, String> = connection.hgetall(format!("members:{}", vault_id)).await?;
+
+    let mut collaborators: Vec<Collaborator> = Vec::new();
+    for (user_id, role) in members {
+        let (forename, email): (String, String) = connection.hget(format!("user:{}", user_id), &["forename", "email"]).await?;
+        collaborators.push(Collaborator { user_id, forename, email, role });
+    }
+    collaborators.sort_by(|a, b| a.email.cmp(&b.email));
+
+    Ok(collaborators)
+}
+
+// CHANGE COLLABORATOR ROLE
+pub async fn change_collaborator_role(vault_id: String, user_id: String, role: String, keys: &UserKeys) -> DbResult<()> {
+    let mut connection = conn().await?;
+
+    let role = Role::parse(&role)?;
+    require_role(&mut connection, &keys.user_id, &vault_id, Role::Owner).await?;
+    ensure_owner_member(&mut connection, &vault_id, &keys.user_id).await?;
+
+    let current = vault_role(&mut connection, &user_id, &vault_id).await?;
+    match current {
+        None => return Err(sharing_error("That user is not a collaborator on this vault")),
+        Some(Role::Owner) if role != Role::Owner => check_other_owner(&mut connection, &vault_id, &user_id).await?,
+        _ => {}
+    }
+
+    // set HASH
+    let _: () = connection.hset(format!("members:{}", vault_id), &user_id, role.as_str()).await?;
+
+    Ok(())
+}
+
+// REVOKE COLLABORATOR (owners can remove anyone, members can remove themselves)
+// the vault key is rotated afterwards, the removed user may still hold a copy of the old one
+pub async fn revoke_collaborator(vault_id: String, user_id: String, keys: &UserKeys) -> DbResult<()> {
+    let mut connection = conn().await?;
+
+    // members whose key was lost in a password reset can still leave, there's nothing they could rotate
+    let vault_key = match open_vault(&mut connection, keys, &vault_id, Role::Viewer).await {
+        Ok(vault_key) => Some(vault_key),
+        Err(DbError::KeyLost) => None,
+        Err(e) => return Err(e),
+    };
+
+    if user_id != keys.user_id {
+        require_role(&mut connection, &keys.user_id, &vault_id, Role::Owner).await?;
+        ensure_owner_member(&mut connection, &vault_id, &keys.user_id).await?;
+    }
+
+    match vault_role(&mut connection, &user_id, &vault_id).await? {
+        None => return Err(DbError::Forbidden),
+        Some(Role::Owner) => check_other_owner(&mut connection, &vault_id, &user_id).await?,
+        _ => {}
+    }
+
+    remove_member(&mut connection, &vault_id, &user_id).await?;
+
+    match vault_key {
+        Some(vault_key) => rotate_vault_key(&mut connection, keys, &vault_id, &vault_key).await,
+        None => Ok(()),
+    }
+}
+
+// removes a member's role, vault key and name key, and the vault from their set last
+// so an interrupted removal is found again
+pub async fn remove_member(connection: &mut MultiplexedConnection, vault_id: &str, user_id: &str) -> RedisResult<()> {
+    // delete HASH fields (role and vault key)
+    let _: () = connection.hdel(format!("members:{}", vault_id), user_id).await?;
+    let _: () = connection.hdel(format!("vault_keys:{}", vault_id), user_id).await?;
+
+    // delete STRING (the collaborator's vault name key)
+    let name_key: Option<String> = connection.hget(format!("vault_index:{}", vault_id), user_id).await?;
+    if let Some(name_key) = name_key {
+        release_name_key(connection, &name_key, vault_id).await?;
+        let _: () = connection.hdel(format!("vault_index:{}", vault_id), user_id).await?;
+    }
+
+    // delete HASH fields (the user's flashcard schedules in every notebook)
+    let notebook_ids: Vec<String> = connection.smembers(format!("notebook:{}", vault_id)).await?;
+    for notebook_id in notebook_ids {
+        delete_user_reviews(connection, &notebook_id, user_id).await?;
+    }
+
+    // delete SET
+    let _: () = connection.srem(format!("vault:{}", user_id), vault_id).await?;
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use redis::{AsyncCommands, RedisResult};
+use redis::aio::MultiplexedConnection;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use uuid::Uuid;
+use super::access::{open_scope, Role};
+use super::client::{conn, now};
+use super::crypto::{decrypt, encrypt, DataKey, UserKeys};
+use super::error::{DbError, DbResult};
+use crate::llm::conversation::{context_budget, continue_conversation, truncate_turns, Thread, ThreadSummary};
+use crate::llm::provider::{ChatMessage, LlmProvider};
+
+// id of the note or notebook a scope ("note:<id>" or "notebook:<id>") points at
+fn scope_id(scope: &str) -> DbResult<&str> {
+    match scope.split_once(':') {
+        Some(("note", id)) | Some(("notebook", id)) => Ok(id),
+        _ => Err(DbError::Invalid("Threads belong to a note or notebook".to_string())),
+    }
+}
+
+// checks access to the thread's note or notebook, threads are encrypted under its vault key
+async fn open_thread_scope(connection: &mut MultiplexedConnection, keys: &UserKeys, scope: &str, required: Role) -> DbResult<DataKey> {
+    scope_id(scope)?;
+    open_scope(connection, keys, scope, required).await
+}
+
+fn not_found() -> DbError {
+    DbError::Invalid("Thread not found".to_string())
+}
+
+// loads a thread after checking access to what it is attached to
+async fn load_thread(connection: &mut MultiplexedConnection, id: &str, keys: &UserKeys, required: Role) -> DbResult<(Thread, DataKey)> {
+    // get HASH
+    let hash: HashMap<String, String> = connection.hgetall(format!("thread:{}", id)).await?;
+    let scope = hash.get("scope").ok_or_else(not_found)?;
+    let content_key = open_thread_scope(connection, keys, scope, required).await?;
+
+    let field = |name: &str| hash.get(name).cloned().unwrap_or_default();
+    let timestamp = |name: &str| hash.get(name).and_then(|value| value.parse().ok()).unwrap_or(0);
+
+    // get LIST
+    let encrypted: Vec<String> = connection.lrange(format!("messages:{}", id), 0, -1).await?;
+    let mut messages: Vec<ChatMessage> = Vec::new();
+    for message in encrypted {
+        let json = decrypt(&content_key, &message)?;
+        messages.push(serde_json::from_str(&json).map_err(|e| DbError::Other(e.to_string()))?);
+    }
+
+    let thread = Thread {
+        id: id.to_string(),
+        scope: scope.clone(),
+        title: decrypt(&content_key, &field("title"))?,
+        system: decrypt(&content_key, &field("system"))?,
+        messages,
+        created_at: timestamp("created_at"),
+        updated_at: timestamp("updated_at"),
+    };
+
+    Ok((thread, content_key))
+}
+
+// GET THREADS (of a note or notebook, most recent first)
+pub async fn get_threads(scope: String, keys: &UserKeys) -> DbResult<Vec<ThreadSummary>> {
+    let mut connection = conn().await?;
+
+    let content_key = open_thread_scope(&mut connection, keys, &scope, Role::Viewer).await?;
+
+    // get SET
+    let thread_ids: Vec<String> = connection.smembers(format!("thread:{}", scope_id(&scope)?)).await?;
+
+    let mut threads: Vec<ThreadSummary> = Vec::new();
+    for id in thread_ids {
+        let (title, updated_at): (Option<String>, Option<i64>) = connection.hget(format!("thread:{}", id), &["title", "updated_at"]).await?;
+        let Some(title) = title else {
+            continue;
+        };
+        let turns: usize = connection.llen(format!("messages:{}", id)).await?;
+
+        threads.push(ThreadSummary {
+            id,
+            title: decrypt(&content_key, &title)?,
+            turns,
+            updated_at: updated_at.unwrap_or(0),
+        });
+    }
+    threads.sort_by_key(|thread| Reverse(thread.updated_at));
+
+    Ok(threads)
+}
+
+// GET THREAD
+pub async fn get_thread(id: String, keys: &UserKeys) -> DbResult<Thread> {
+    let mut connection = conn().await?;
+    Ok(load_thread(&mut connection, &id, keys, Role::Viewer).await?.0)
+}
+
+// CONTINUE THREAD (a new one when no id is given), returns the thread with the answer added
+pub async fn continue_thread(scope: String, thread_id: Option<String>, prompt: String, provider: &dyn LlmProvider, model: Option<String>, keys: &UserKeys) -> DbResult<Thread> {
+    let mut connection = conn().await?;
+
+    let (mut thread, content_key) = match thread_id {
+        Some(id) => {
+            let (thread, content_key) = load_thread(&mut connection, &id, keys, Role::Editor).await?;
+            if thread.scope != scope {
+                return Err(not_found());
+            }
+            (thread, content_key)
+        }
+        None => {
+            let content_key = open_thread_scope(&mut connection, keys, &scope, Role::Editor).await?;
+            (Thread::new(Uuid::new_v4().to_string(), scope.clone(), &prompt, now()), content_key)
+        }
+    };
+
+    let is_new = thread.messages.is_empty();
+    continue_conversation(&mut thread, prompt, provider, model, now()).await?;
+
+    let thread_key = format!("thread:{}", thread.id);
+
+    if is_new {
+        // create HASH + SET
+        let _: () = connection.hset_multiple(&thread_key, &[
+            ("scope", thread.scope.clone()),
+            ("title", encrypt(&content_key, &thread.title)?),
+            ("system", encrypt(&content_key, &thread.system)?),
+            ("created_at", thread.created_at.to_string()),
+        ]).await?;
+        let _: () = connection.sadd(format!("thread:{}", scope_id(&scope)?), &thread.id).await?;
+    }
+
+    // push LIST (the prompt and its answer)
+    let mut turns: Vec<String> = Vec::new();
+    for message in &thread.messages[thread.messages.len() - 2..] {
+        let json = serde_json::to_string(message).map_err(|e| DbError::Other(e.to_string()))?;
+        turns.push(encrypt(&content_key, &json)?);
+    }
+    let _: () = connection.rpush(format!("messages:{}", thread.id), turns).await?;
+
+    // set HASH
+    let _: () = connection.hset(&thread_key, "updated_at", thread.updated_at).await?;
+
+    Ok(thread)
+}
+
+// TRUNCATE THREAD (drops the oldest turns beyond the budget, returns how many went)
+pub async fn truncate_thread(id: String, max_tokens: Option<usize>, keys: &UserKeys) -> DbResult<usize> {
+    let mut connection = conn().await?;
+
+    let (mut thread, _) = load_thread(&mut connection, &id, keys, Role::Editor).await?;
+    let removed = truncate_turns(&mut thread, max_tokens.unwrap_or_else(context_budget));
+
+    // trim LIST
+    if removed > 0 {
+        let _: () = connection.ltrim(format!("messages:{}", id), removed as isize, -1).await?;
+    }
+
+    Ok(removed)
+}
+
+// DELETE THREAD
+pub async fn delete_thread(id: String, keys: &UserKeys) -> DbResult<()> {
+    let mut connection = conn().await?;
+
+    let (thread, _) = load_thread(&mut connection, &id, keys, Role::Editor).await?;
+
+    // delete HASH + LIST + SET member
+    let _: () = connection.del(&[format!("thread:{}", id), format!("messages:{}", id)]).await?;
+    let _: () = connection.srem(format!("thread:{}", scope_id(&thread.scope)?), &id).await?;
+
+    Ok(())
+}
+
+// DELETE SCOPE THREADS (when their note or notebook is deleted), returns how many went
+pub async fn delete_scope_threads(connection: &mut MultiplexedConnection, scope_id: &str) -> RedisResult<usize> {
+    let set_key = format!("thread:{}", scope_id);
+
+    // get SET
+    let thread_ids: Vec<String> = connection.smembers(&set_key).await?;
+    for id in &thread_ids {
+        // delete HASH + LIST
+        let _: () = connection.del(&[format!("thread:{}", id), format!("messages:{}", id)]).await?;
+    }
+
+    // delete SET
+    let _: () = connection.del(&set_key).await?;
+
+    Ok(thread_ids.len())
+}
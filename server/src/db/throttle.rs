@@ -0,0 +1,100 @@
+use redis::{AsyncCommands, RedisResult, Script};
+use redis::aio::MultiplexedConnection;
+use super::client::{env_integer, env_seconds, now};
+use super::crypto::hash_token;
+use super::error::{DbError, DbResult};
+
+// checks every counter and, if none of them asks for a wait, counts the attempt on all of them in one step,
+// so parallel logins can't all get past the check before the first failure is recorded
+// KEYS: failure counters   ARGV: now, max attempts, base delay, max delay, lockout, expiry
+const RESERVE_ATTEMPT: &str = r"
+local now = tonumber(ARGV[1])
+local wait = 0
+for _, key in ipairs(KEYS) do
+    local count = tonumber(redis.call('HGET', key, 'count') or 0)
+    local last = tonumber(redis.call('HGET', key, 'last') or 0)
+    if count > 0 then
+        local delay
+        if count >= tonumber(ARGV[2]) then
+            delay = tonumber(ARGV[5])
+        else
+            delay = math.min(tonumber(ARGV[3]) * 2 ^ math.min(count - 1, 30), tonumber(ARGV[4]))
+        end
+        wait = math.max(wait, last + delay - now)
+    end
+end
+if wait > 0 then
+    return wait
+end
+for _, key in ipairs(KEYS) do
+    redis.call('HINCRBY', key, 'count', 1)
+    redis.call('HSET', key, 'last', now)
+    redis.call('EXPIRE', key, ARGV[6])
+end
+return 0
+";
+
+// failures allowed before the email or client is locked out (default 5)
+fn max_attempts() -> i64 {
+    env_integer("LOGIN_MAX_ATTEMPTS", 5)
+}
+
+// delay after the first failure, doubled after each one after it (default 1 second)
+fn base_delay() -> i64 {
+    env_seconds("LOGIN_BASE_DELAY", 1)
+}
+
+// longest delay before the lockout starts (default 1 minute)
+fn max_delay() -> i64 {
+    env_seconds("LOGIN_MAX_DELAY", 60)
+}
+
+// how long a lockout lasts (default 15 minutes)
+fn lockout() -> i64 {
+    env_seconds("LOGIN_LOCKOUT", 900)
+}
+
+// how long failures are remembered after the last one (default 15 minutes)
+fn failure_window() -> i64 {
+    env_seconds("LOGIN_FAILURE_WINDOW", 900)
+}
+
+// failure counters for the email being tried and the client trying it
+fn failure_keys(email: &str, client: &str) -> [String; 2] {
+    [
+        format!("login_failures:email:{}", email),
+        format!("login_failures:client:{}", hash_token(client)),
+    ]
+}
+
+// RESERVE LOGIN ATTEMPT (before the password or code is verified, counted as a failure until cleared)
+pub async fn reserve_attempt(connection: &mut MultiplexedConnection, email: &str, client: &str) -> DbResult<()> {
+    let script = Script::new(RESERVE_ATTEMPT);
+    let mut invocation = script.prepare_invoke();
+    for key in failure_keys(email, client) {
+        invocation.key(key);
+    }
+
+    // set HASH + EXPIRY (unless a wait is due)
+    let wait: i64 = invocation
+        .arg(now())
+        .arg(max_attempts())
+        .arg(base_delay())
+        .arg(max_delay())
+        .arg(lockout())
+        .arg(failure_window().max(lockout()))
+        .invoke_async(connection)
+        .await?;
+
+    if wait > 0 {
+        return Err(DbError::TooManyAttempts(wait as u64));
+    }
+
+    Ok(())
+}
+
+// CLEAR LOGIN FAILURES (after a successful login)
+pub async fn clear_failures(connection: &mut MultiplexedConnection, email: &str, client: &str) -> RedisResult<()> {
+    // delete HASH
+    connection.del(&failure_keys(email, client)).await
+}
@@ -0,0 +1,205 @@
+use redis::{AsyncCommands, RedisResult};
+use redis::aio::MultiplexedConnection;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use super::client::{conn, now};
+use super::crypto::{decrypt, encrypt, generate_token, hash_token, UserKeys};
+use super::error::{DbError, DbResult};
+use super::models::TotpEnrolment;
+
+const ISSUER: &str = "Neuro";
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+// codes from one step either side are accepted to allow for clock drift
+const DRIFT_STEPS: i64 = 1;
+const RECOVERY_CODES: usize = 10;
+
+// RFC 4648 base32 (no padding), the secret format authenticator apps expect
+fn base32(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+    let mut output = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits: u32 = 0;
+
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+
+        while bits >= 5 {
+            output.push(ALPHABET[((buffer >> (bits - 5)) & 31) as usize] as char);
+            bits -= 5;
+        }
+    }
+
+    if bits > 0 {
+        output.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+
+    output
+}
+
+// percent-encodes a value for the otpauth URI
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+// RFC 4226 HOTP code for a counter (RFC 6238 uses the time step as the counter)
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+
+    binary % 10u32.pow(DIGITS)
+}
+
+// time step a code matches, if any
+fn matching_step(secret: &[u8], code: &str) -> Option<i64> {
+    let code: u32 = code.trim().parse().ok()?;
+    let current = now() / STEP_SECONDS;
+
+    (current - DRIFT_STEPS..=current + DRIFT_STEPS).find(|step| hotp(secret, *step as u64) == code)
+}
+
+// recovery codes are compared without dashes, spaces or case
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_lowercase()
+}
+
+// reads and decrypts a secret field from the user hash
+async fn read_secret(connection: &mut MultiplexedConnection, keys: &UserKeys, field: &str) -> DbResult<Option<Vec<u8>>> {
+    // get HASH
+    let secret: Option<String> = connection.hget(format!("user:{}", keys.user_id), field).await?;
+
+    match secret {
+        Some(secret) => hex::decode(decrypt(&keys.data_key, &secret)?)
+            .map(Some)
+            .map_err(|_| DbError::Other("Invalid two-factor secret".to_string())),
+        None => Ok(None),
+    }
+}
+
+// IS TWO-FACTOR ENABLED
+pub async fn two_factor_enabled(connection: &mut MultiplexedConnection, user_id: &str) -> RedisResult<bool> {
+    connection.hexists(format!("user:{}", user_id), "totp_secret").await
+}
+
+// BEGIN ENROLMENT (secret and otpauth URI, not active until a code is confirmed)
+pub async fn begin_enrolment(keys: &UserKeys) -> DbResult<TotpEnrolment> {
+    let mut connection = conn().await?;
+    let user_key = format!("user:{}", keys.user_id);
+
+    if two_factor_enabled(&mut connection, &keys.user_id).await? {
+        return Err(DbError::Invalid("Two-factor authentication is already enabled".to_string()));
+    }
+
+    // 160-bit secret, as recommended by RFC 4226
+    let mut secret = [0u8; 20];
+    OsRng.fill_bytes(&mut secret);
+
+    // set HASH (encrypted, like the rest of the user's data)
+    let _: () = connection.hset(&user_key, "totp_pending", encrypt(&keys.data_key, &hex::encode(secret))?).await?;
+
+    // get HASH
+    let email: String = connection.hget(&user_key, "email").await?;
+
+    let secret = base32(&secret);
+    let uri = format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        uri_encode(ISSUER), uri_encode(&email), secret, uri_encode(ISSUER), DIGITS, STEP_SECONDS
+    );
+
+    Ok(TotpEnrolment { secret, uri })
+}
+
+// CONFIRM ENROLMENT (enables two-factor and returns the one-time recovery codes)
+pub async fn confirm_enrolment(code: String, keys: &UserKeys) -> DbResult<Vec<String>> {
+    let mut connection = conn().await?;
+    let user_key = format!("user:{}", keys.user_id);
+
+    let secret = read_secret(&mut connection, keys, "totp_pending")
+        .await?
+        .ok_or_else(|| DbError::Invalid("Start two-factor setup first".to_string()))?;
+
+    let step = matching_step(&secret, &code).ok_or_else(|| DbError::Invalid("Invalid code".to_string()))?;
+
+    // set HASH (pending secret becomes the active one)
+    let pending: String = connection.hget(&user_key, "totp_pending").await?;
+    let _: () = connection.hset_multiple(&user_key, &[("totp_secret", pending), ("totp_last_step", step.to_string())]).await?;
+    let _: () = connection.hdel(&user_key, "totp_pending").await?;
+
+    let mut codes: Vec<String> = Vec::new();
+    let recovery_key = format!("recovery:{}", keys.user_id);
+
+    // delete SET (codes from an earlier enrolment)
+    let _: () = connection.del(&recovery_key).await?;
+
+    for _ in 0..RECOVERY_CODES {
+        let code = generate_token()[..10].to_string();
+
+        // create SET (hashed codes only)
+        let _: () = connection.sadd(&recovery_key, hash_token(&code)).await?;
+        codes.push(format!("{}-{}", &code[..5], &code[5..]));
+    }
+
+    Ok(codes)
+}
+
+// VERIFY CODE (authenticator code, or a recovery code which is used up)
+pub async fn verify_code(connection: &mut MultiplexedConnection, code: &str, keys: &UserKeys) -> DbResult<bool> {
+    let user_key = format!("user:{}", keys.user_id);
+
+    if let Some(secret) = read_secret(connection, keys, "totp_secret").await? {
+        if let Some(step) = matching_step(&secret, code) {
+            // a code can only be used once
+            let last: Option<i64> = connection.hget(&user_key, "totp_last_step").await?;
+            if last.is_some_and(|last| step <= last) {
+                return Ok(false);
+            }
+
+            // set HASH
+            let _: () = connection.hset(&user_key, "totp_last_step", step).await?;
+            return Ok(true);
+        }
+    }
+
+    // delete SET member (single use)
+    let removed: bool = connection.srem(format!("recovery:{}", keys.user_id), hash_token(&normalize_recovery_code(code))).await?;
+    Ok(removed)
+}
+
+// HAS RECOVERY CODE (codes are stored hashed, so this works without the user's keys, as in a password reset)
+// only checks the code, the caller uses it up
+pub async fn has_recovery_code(connection: &mut MultiplexedConnection, user_id: &str, code: &str) -> RedisResult<bool> {
+    connection.sismember(format!("recovery:{}", user_id), hash_token(&normalize_recovery_code(code))).await
+}
+
+// queues removing the secret and recovery codes
+pub fn clear_two_factor(pipe: &mut redis::Pipeline, user_id: &str) {
+    pipe.hdel(format!("user:{}", user_id), &["totp_secret", "totp_pending", "totp_last_step"]).ignore();
+    pipe.del(format!("recovery:{}", user_id)).ignore();
+}
+
+// DISABLE TWO-FACTOR (the caller must have re-checked the password)
+pub async fn disable_two_factor(connection: &mut MultiplexedConnection, user_id: &str) -> RedisResult<()> {
+    // delete HASH fields + SET
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    clear_two_factor(&mut pipe, user_id);
+
+    pipe.query_async(connection).await
+}
@@ -0,0 +1,126 @@
+use super::error::{DbError, DbResult};
+use crate::llm::ai::PROVIDERS;
+use crate::llm::settings::AI_SETTINGS;
+
+// longest address allowed by RFC 5321
+const MAX_EMAIL_LENGTH: usize = 254;
+const MAX_FORENAME_LENGTH: usize = 50;
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+// emails are stored trimmed and lowercased so "Jo@Mail.com " and "jo@mail.com" are one account
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+// checks a (normalized) email looks like local@domain.tld
+pub fn validate_email(email: &str) -> DbResult<()> {
+    let invalid = || DbError::Invalid("Enter a valid email".to_string());
+
+    if email.len() > MAX_EMAIL_LENGTH || email.chars().any(char::is_whitespace) {
+        return Err(invalid());
+    }
+
+    let (local, domain) = email.split_once('@').ok_or_else(invalid)?;
+    let domain_valid = domain.contains('.')
+        && !domain.contains('@')
+        && domain.split('.').all(|label| !label.is_empty());
+
+    if local.is_empty() || !domain_valid {
+        return Err(invalid());
+    }
+
+    Ok(())
+}
+
+// trims the forename and checks its length
+pub fn validate_forename(forename: &str) -> DbResult<String> {
+    let forename = forename.trim();
+
+    match forename.chars().count() {
+        0 => Err(DbError::Invalid("Please enter your forename".to_string())),
+        length if length > MAX_FORENAME_LENGTH => Err(DbError::Invalid(format!(
+            "Forename must be at most {} characters",
+            MAX_FORENAME_LENGTH
+        ))),
+        _ => Ok(forename.to_string()),
+    }
+}
+
+// checks a new password's length
+pub fn validate_password(password: &str) -> DbResult<()> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(DbError::Invalid(format!("Password must be at least {} characters", MIN_PASSWORD_LENGTH)));
+    }
+
+    Ok(())
+}
+
+// preferences a user can set, anything else is rejected
+pub const PREFERENCES: [&str; 6] = ["theme", "default_vault", "ai_provider", "ai_model", "autosave_interval", "semantic_index"];
+const THEMES: [&str; 3] = ["system", "light", "dark"];
+const MAX_AI_MODEL_LENGTH: usize = 100;
+// autosave interval bounds, in seconds
+const AUTOSAVE_INTERVAL: std::ops::RangeInclusive<u32> = 1..=3600;
+
+// trims a preference value and checks it suits the preference, an empty value clears it
+// (default_vault is checked against the user's vaults by the caller)
+pub fn validate_preference(name: &str, value: &str) -> DbResult<Option<String>> {
+    let value = value.trim();
+
+    if !PREFERENCES.contains(&name) {
+        return Err(DbError::Invalid(format!("Unknown preference: {}", name)));
+    }
+
+    match name {
+        _ if value.is_empty() => Ok(None),
+        "theme" if !THEMES.contains(&value) => Err(DbError::Invalid(format!("Theme must be one of: {}", THEMES.join(", ")))),
+        "ai_provider" if !PROVIDERS.contains(&value) => Err(DbError::Invalid(format!("AI provider must be one of: {}", PROVIDERS.join(", ")))),
+        "ai_model" if value.chars().count() > MAX_AI_MODEL_LENGTH => Err(DbError::Invalid(format!(
+            "AI model must be at most {} characters",
+            MAX_AI_MODEL_LENGTH
+        ))),
+        "autosave_interval" if !value.parse().is_ok_and(|seconds| AUTOSAVE_INTERVAL.contains(&seconds)) => Err(DbError::Invalid(format!(
+            "Autosave interval must be between {} and {} seconds",
+            AUTOSAVE_INTERVAL.start(),
+            AUTOSAVE_INTERVAL.end()
+        ))),
+        "semantic_index" if !["on", "off"].contains(&value) => Err(DbError::Invalid("Semantic index must be on or off".to_string())),
+        _ => Ok(Some(value.to_string())),
+    }
+}
+
+const MAX_API_KEY_LENGTH: usize = 512;
+const MAX_BASE_URL_LENGTH: usize = 2048;
+const TEMPERATURE: std::ops::RangeInclusive<f32> = 0.0..=2.0;
+const MAX_TOKENS: std::ops::RangeInclusive<u32> = 1..=200_000;
+
+// trims an AI setting and checks it suits the setting, an empty value clears it
+pub fn validate_ai_setting(name: &str, value: &str) -> DbResult<Option<String>> {
+    let value = value.trim();
+
+    if !AI_SETTINGS.contains(&name) {
+        return Err(DbError::Invalid(format!("Unknown AI setting: {}", name)));
+    }
+
+    match name {
+        _ if value.is_empty() => Ok(None),
+        "api_key" if value.len() > MAX_API_KEY_LENGTH || value.chars().any(char::is_whitespace) => {
+            Err(DbError::Invalid("Enter a valid API key".to_string()))
+        }
+        "base_url" if value.len() > MAX_BASE_URL_LENGTH || !(value.starts_with("https://") || value.starts_with("http://")) => {
+            Err(DbError::Invalid("Server URL must start with http:// or https://".to_string()))
+        }
+        "base_url" => Ok(Some(value.trim_end_matches('/').to_string())),
+        "temperature" if !value.parse().is_ok_and(|temperature| TEMPERATURE.contains(&temperature)) => Err(DbError::Invalid(format!(
+            "Temperature must be between {} and {}",
+            TEMPERATURE.start(),
+            TEMPERATURE.end()
+        ))),
+        "max_tokens" if !value.parse().is_ok_and(|tokens| MAX_TOKENS.contains(&tokens)) => Err(DbError::Invalid(format!(
+            "Max tokens must be between {} and {}",
+            MAX_TOKENS.start(),
+            MAX_TOKENS.end()
+        ))),
+        _ => Ok(Some(value.to_string())),
+    }
+}
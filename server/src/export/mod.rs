@@ -0,0 +1,64 @@
+pub mod render;
+pub mod site;
+
+use site::{SiteNote, SiteSection};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tauri::command;
+use crate::db::attachments::attachment_data_uris;
+use crate::db::crypto::UserKeys;
+
+// renders a local note to a standalone HTML file
+#[command]
+pub fn render_note(path: &str, output: &str) -> Result<(), String> {
+    let path = Path::new(path);
+    let note = SiteNote {
+        name: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+        markdown: fs::read_to_string(path).map_err(|e| e.to_string())?,
+        base: path.parent().map(Path::to_path_buf),
+        attachments: HashMap::new(),
+    };
+
+    site::export_note(&note, Path::new(output))
+}
+
+// exports a local notebook as a static site, returns the index page path
+#[command]
+pub fn export_notebook(path: &str, output: &str) -> Result<String, String> {
+    let section = site::load_notebook(Path::new(path))?;
+    let title = section.name.clone();
+
+    site::export_site(&title, &[section], Path::new(output))
+        .map(|index| index.to_string_lossy().into_owned())
+}
+
+// exports a local vault as a static site, returns the index page path
+#[command]
+pub fn export_vault(path: &str, output: &str) -> Result<String, String> {
+    let path = Path::new(path);
+    let sections: Vec<SiteSection> = site::load_vault(path)?;
+    let title = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+    site::export_site(&title, &sections, Path::new(output))
+        .map(|index| index.to_string_lossy().into_owned())
+}
+
+// builds a remote note, inlining the attachments it references
+pub async fn remote_note(name: String, markdown: String, keys: &UserKeys) -> Result<SiteNote, String> {
+    let attachments = attachment_data_uris(&markdown, keys)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(SiteNote { name, markdown, base: None, attachments })
+}
+
+// builds a section from remote (name, content) pairs
+pub async fn remote_section(name: String, notes: Vec<(String, String)>, keys: &UserKeys) -> Result<SiteSection, String> {
+    let mut section = SiteSection { name, notes: Vec::new() };
+    for (name, markdown) in notes {
+        section.notes.push(remote_note(name, markdown, keys).await?);
+    }
+
+    Ok(section)
+}
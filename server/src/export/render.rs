@@ -0,0 +1,270 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::attachments::mime_type;
+
+// stylesheet embedded in every exported page so the HTML stays self-contained
+const STYLE: &str = r#"
+body { margin: 0; font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; background: #18181b; color: #f4f4f5; }
+.layout { display: flex; min-height: 100vh; }
+nav.sidebar { width: 16rem; padding: 2rem 1.5rem; background: #27272a; }
+nav.sidebar a { display: block; color: #d4d4d8; text-decoration: none; padding: 0.2rem 0; }
+nav.sidebar a.active, nav.sidebar a:hover { color: #f97316; }
+nav.sidebar h2 { font-size: 0.8rem; text-transform: uppercase; color: #a1a1aa; margin-top: 1.5rem; }
+main { flex: 1; max-width: 50rem; padding: 2rem 3rem; line-height: 1.6; }
+main a { color: #f97316; }
+pre, code { background: #27272a; border-radius: 4px; }
+pre { padding: 1rem; overflow-x: auto; }
+code { padding: 0.1rem 0.3rem; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #3f3f46; padding: 0.4rem 0.8rem; }
+blockquote { border-left: 3px solid #f97316; margin-left: 0; padding-left: 1rem; color: #a1a1aa; }
+img { max-width: 100%; }
+.pager { display: flex; justify-content: space-between; margin-top: 3rem; border-top: 1px solid #3f3f46; padding-top: 1rem; }
+"#;
+
+// markdown extensions used by every render (CommonMark + GFM tables, task lists, footnotes)
+fn options() -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options
+}
+
+// escapes text placed directly into the page template
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// scheme of a URL ("https" for "https://…"), none for relative links
+fn scheme(dest: &str) -> Option<String> {
+    let dest: String = dest.chars().filter(|c| !c.is_whitespace() && !c.is_control()).collect();
+    let (scheme, _) = dest.split_once(':')?;
+
+    let mut chars = scheme.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid.then(|| scheme.to_lowercase())
+}
+
+// true for links that point outside the exported notes (web, mail, anchors)
+fn is_external(dest: &str) -> bool {
+    scheme(dest).is_some() || dest.starts_with('#')
+}
+
+// links may only be relative or web and mail ones, anything else ("javascript:", "data:text/html", …)
+// could run script when followed
+fn is_allowed_link(dest: &str) -> bool {
+    match scheme(dest) {
+        Some(scheme) => ["http", "https", "mailto"].contains(&scheme.as_str()),
+        None => true,
+    }
+}
+
+// decodes %XX sequences so "My%20Note.md" matches the note "My Note.md"
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// rewrites links to other notes so they point at the exported page
+fn resolve_link<'a>(dest: CowStr<'a>, links: &HashMap<String, String>) -> CowStr<'a> {
+    if !is_allowed_link(&dest) {
+        return "#".into();
+    }
+    if is_external(&dest) {
+        return dest;
+    }
+
+    let (target, fragment) = match dest.split_once('#') {
+        Some((target, fragment)) => (target, Some(fragment)),
+        None => (&*dest, None),
+    };
+
+    let name = percent_decode(target.trim_start_matches("./"));
+    match links.get(&name) {
+        Some(page) => match fragment {
+            Some(fragment) => format!("{}#{}", page, fragment).into(),
+            None => page.clone().into(),
+        },
+        None => dest,
+    }
+}
+
+// local image path inside `base`, none for paths leading out of it ("../", absolute paths, symlinks)
+fn contained_path(base: &Path, dest: &str) -> Option<PathBuf> {
+    let base = base.canonicalize().ok()?;
+    let path = base.join(percent_decode(dest)).canonicalize().ok()?;
+    path.starts_with(&base).then_some(path)
+}
+
+// embeds images as data URIs so the page has no external files
+// synced attachments come from `attachments`, local ones are read relative to `base` and never from outside it
+fn inline_image<'a>(dest: CowStr<'a>, base: Option<&Path>, attachments: &HashMap<String, String>) -> CowStr<'a> {
+    if let Some(uri) = attachments.get(&*dest) {
+        return uri.clone().into();
+    }
+    if !is_allowed_link(&dest) && !dest.starts_with("data:image/") {
+        return "".into();
+    }
+
+    let base = match base {
+        Some(base) if !is_external(&dest) => base,
+        _ => return dest,
+    };
+
+    let Some(path) = contained_path(base, &dest) else {
+        return "".into();
+    };
+    let mime = mime_type(&path.to_string_lossy());
+    if !mime.starts_with("image/") {
+        return "".into();
+    }
+
+    match fs::read(&path) {
+        Ok(bytes) => format!("data:{};base64,{}", mime, STANDARD.encode(bytes)).into(),
+        Err(_) => "".into(),
+    }
+}
+
+// Renders markdown into an HTML fragment
+// `links` maps note names to exported page names, `base` is the folder relative images are read from
+// and `attachments` maps synced attachment references to data URIs
+// raw HTML is shown as text, notes can come from other vault members and the page runs in the app's webview
+pub fn render_markdown(
+    markdown: &str,
+    links: &HashMap<String, String>,
+    base: Option<&Path>,
+    attachments: &HashMap<String, String>,
+) -> String {
+    let parser = Parser::new_ext(markdown, options()).map(|event| match event {
+        Event::Start(Tag::Link { link_type, dest_url, title, id }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: resolve_link(dest_url, links),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image { link_type, dest_url, title, id }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: inline_image(dest_url, base, attachments),
+            title,
+            id,
+        }),
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        event => event,
+    });
+
+    let mut body = String::new();
+    html::push_html(&mut body, parser);
+    body
+}
+
+// Wraps rendered markdown in a complete HTML document
+// `nav` is optional sidebar markup, `pager` optional previous/next links
+pub fn html_page(title: &str, body: &str, nav: Option<&str>, pager: Option<&str>) -> String {
+    let sidebar = nav
+        .map(|nav| format!("<nav class=\"sidebar\">{}</nav>", nav))
+        .unwrap_or_default();
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<div class=\"layout\">\n{}\n\
+         <main>\n{}\n{}\n</main>\n</div>\n</body>\n</html>\n",
+        escape_html(title),
+        STYLE,
+        sidebar,
+        body,
+        pager.unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn render(markdown: &str) -> String {
+        render_markdown(markdown, &HashMap::new(), None, &HashMap::new())
+    }
+
+    // notebook folder with an image inside it and a secret file next to it
+    fn notebook() -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!("neuro-render-{}", Uuid::new_v4()));
+        let notebook = root.join("notebook");
+        fs::create_dir_all(&notebook).unwrap();
+        fs::write(notebook.join("inside.png"), b"png").unwrap();
+        fs::write(root.join("secret.png"), b"secret").unwrap();
+        (root, notebook)
+    }
+
+    #[test]
+    fn template_text_is_escaped() {
+        assert_eq!(escape_html("<a href=\"x\">Tom & 'Jerry'</a>"), "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;");
+
+        let page = html_page("<script>", "", None, None);
+        assert!(page.contains("<title>&lt;script&gt;</title>"));
+    }
+
+    #[test]
+    fn raw_html_is_shown_as_text() {
+        let body = render("<script>alert(1)</script>\n\nHi <img src=x onerror=alert(1)>");
+        assert!(!body.contains("<script>"));
+        assert!(!body.contains("<img"));
+        assert!(body.contains("&lt;script&gt;"));
+    }
+
+    #[test]
+    fn script_links_are_stripped() {
+        for dest in ["javascript:alert(1)", " JavaScript:alert(1)", "java\tscript:alert(1)", "vbscript:msgbox", "data:text/html;base64,PHNjcmlwdD4="] {
+            let body = render(&format!("[link](<{}>)", dest));
+            assert!(body.contains("href=\"#\""), "{} was kept: {}", dest, body);
+        }
+    }
+
+    #[test]
+    fn web_mail_and_relative_links_are_kept() {
+        assert!(render("[a](https://example.com)").contains("href=\"https://example.com\""));
+        assert!(render("[a](mailto:me@example.com)").contains("href=\"mailto:me@example.com\""));
+        assert!(render("[a](other.md#part)").contains("href=\"other.md#part\""));
+    }
+
+    #[test]
+    fn images_stay_inside_the_notebook() {
+        let (root, notebook) = notebook();
+        let render = |markdown: &str| render_markdown(markdown, &HashMap::new(), Some(&notebook), &HashMap::new());
+
+        assert!(render("![](inside.png)").contains("src=\"data:image/png;base64,"));
+        assert!(!render("![](../secret.png)").contains("data:image/png"));
+        assert!(!render("![](..%2Fsecret.png)").contains("data:image/png"));
+
+        let absolute = root.join("secret.png");
+        assert!(!render(&format!("![](<{}>)", absolute.display())).contains("data:image/png"));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
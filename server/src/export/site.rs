@@ -0,0 +1,256 @@
+use super::render::{escape_html, html_page, render_markdown};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// A single note to export
+pub struct SiteNote {
+    pub name: String,
+    pub markdown: String,
+    pub base: Option<PathBuf>, // folder relative images are resolved from (local notes only)
+    pub attachments: HashMap<String, String>, // synced attachment references -> data URIs
+}
+
+// A group of notes, one per notebook
+pub struct SiteSection {
+    pub name: String,
+    pub notes: Vec<SiteNote>,
+}
+
+// exported page with its generated file name
+struct Page<'a> {
+    section: usize,
+    file: String,
+    note: &'a SiteNote,
+}
+
+// note name without the markdown extension
+pub fn note_title(name: &str) -> &str {
+    name.strip_suffix(".md").unwrap_or(name)
+}
+
+// turns a note or notebook name into a safe file name
+fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let slug = slug.trim_matches('-').to_string();
+    if slug.is_empty() { "note".to_string() } else { slug }
+}
+
+// generates a unique page file name
+fn unique_file(slug: String, taken: &mut HashSet<String>) -> String {
+    let mut file = format!("{}.html", slug);
+    let mut count = 2;
+    while !taken.insert(file.clone()) {
+        file = format!("{}-{}.html", slug, count);
+        count += 1;
+    }
+    file
+}
+
+// Loads every markdown note within a local notebook folder
+pub fn load_notebook(path: &Path) -> Result<SiteSection, String> {
+    let mut notes: Vec<SiteNote> = Vec::new();
+
+    for entry in fs::read_dir(path).map_err(|e| e.to_string())? {
+        let entry_path = entry.map_err(|e| e.to_string())?.path();
+        let is_note = entry_path.is_file() && entry_path.extension().is_some_and(|ext| ext == "md");
+
+        if is_note {
+            notes.push(SiteNote {
+                name: entry_path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+                markdown: fs::read_to_string(&entry_path).map_err(|e| e.to_string())?,
+                base: Some(path.to_path_buf()),
+                attachments: HashMap::new(),
+            });
+        }
+    }
+    notes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(SiteSection {
+        name: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+        notes,
+    })
+}
+
+// Loads every notebook within a local vault folder (hidden folders are skipped)
+pub fn load_vault(path: &Path) -> Result<Vec<SiteSection>, String> {
+    let mut sections: Vec<SiteSection> = Vec::new();
+
+    for entry in fs::read_dir(path).map_err(|e| e.to_string())? {
+        let entry_path = entry.map_err(|e| e.to_string())?.path();
+        let hidden = entry_path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+
+        if entry_path.is_dir() && !hidden {
+            sections.push(load_notebook(&entry_path)?);
+        }
+    }
+    sections.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(sections)
+}
+
+// link targets for a section: notes in the same notebook win over same-named notes elsewhere
+fn link_map(pages: &[Page], section: usize) -> HashMap<String, String> {
+    let mut links: HashMap<String, String> = HashMap::new();
+    let same_section = pages.iter().filter(|page| page.section == section);
+    let other_sections = pages.iter().filter(|page| page.section != section);
+
+    for page in same_section.chain(other_sections) {
+        for key in [page.note.name.clone(), note_title(&page.note.name).to_string()] {
+            links.entry(key).or_insert_with(|| page.file.clone());
+        }
+    }
+    links
+}
+
+// sidebar listing every section and note, highlighting the current page
+fn sidebar(title: &str, sections: &[SiteSection], pages: &[Page], current: Option<&str>) -> String {
+    let mut nav = format!("<a href=\"index.html\"><strong>{}</strong></a>", escape_html(title));
+
+    for (index, section) in sections.iter().enumerate() {
+        if sections.len() > 1 {
+            nav.push_str(&format!("<h2>{}</h2>", escape_html(&section.name)));
+        }
+
+        for page in pages.iter().filter(|page| page.section == index) {
+            let class = if Some(page.file.as_str()) == current { " class=\"active\"" } else { "" };
+            nav.push_str(&format!(
+                "<a href=\"{}\"{}>{}</a>",
+                page.file,
+                class,
+                escape_html(note_title(&page.note.name))
+            ));
+        }
+    }
+    nav
+}
+
+// previous/next links between notes
+fn pager(pages: &[Page], position: usize) -> String {
+    let previous = position
+        .checked_sub(1)
+        .and_then(|i| pages.get(i))
+        .map(|page| format!("<a href=\"{}\">&larr; {}</a>", page.file, escape_html(note_title(&page.note.name))))
+        .unwrap_or_else(|| "<span></span>".to_string());
+
+    let next = pages
+        .get(position + 1)
+        .map(|page| format!("<a href=\"{}\">{} &rarr;</a>", page.file, escape_html(note_title(&page.note.name))))
+        .unwrap_or_else(|| "<span></span>".to_string());
+
+    format!("<div class=\"pager\">{}{}</div>", previous, next)
+}
+
+// index page body listing every note
+fn index_body(title: &str, sections: &[SiteSection], pages: &[Page]) -> String {
+    let mut body = format!("<h1>{}</h1>", escape_html(title));
+
+    for (index, section) in sections.iter().enumerate() {
+        if sections.len() > 1 {
+            body.push_str(&format!("<h2>{}</h2>", escape_html(&section.name)));
+        }
+
+        body.push_str("<ul>");
+        for page in pages.iter().filter(|page| page.section == index) {
+            body.push_str(&format!(
+                "<li><a href=\"{}\">{}</a></li>",
+                page.file,
+                escape_html(note_title(&page.note.name))
+            ));
+        }
+        body.push_str("</ul>");
+    }
+    body
+}
+
+// Writes a static site (index page + one page per note) into `out_dir`
+// returns the path of the generated index page
+pub fn export_site(title: &str, sections: &[SiteSection], out_dir: &Path) -> Result<PathBuf, String> {
+    fs::create_dir_all(out_dir).map_err(|e| e.to_string())?;
+
+    // assign every note a unique page name
+    let mut taken: HashSet<String> = HashSet::from(["index.html".to_string()]);
+    let mut pages: Vec<Page> = Vec::new();
+    for (index, section) in sections.iter().enumerate() {
+        for note in &section.notes {
+            let slug = if sections.len() > 1 {
+                format!("{}-{}", slugify(&section.name), slugify(note_title(&note.name)))
+            } else {
+                slugify(note_title(&note.name))
+            };
+
+            pages.push(Page { section: index, file: unique_file(slug, &mut taken), note });
+        }
+    }
+
+    // note pages
+    for (position, page) in pages.iter().enumerate() {
+        let links = link_map(&pages, page.section);
+        let body = render_markdown(&page.note.markdown, &links, page.note.base.as_deref(), &page.note.attachments);
+        let nav = sidebar(title, sections, &pages, Some(&page.file));
+        let html = html_page(note_title(&page.note.name), &body, Some(&nav), Some(&pager(&pages, position)));
+
+        fs::write(out_dir.join(&page.file), html).map_err(|e| e.to_string())?;
+    }
+
+    // index page
+    let index = out_dir.join("index.html");
+    let nav = sidebar(title, sections, &pages, None);
+    let html = html_page(title, &index_body(title, sections, &pages), Some(&nav), None);
+    fs::write(&index, html).map_err(|e| e.to_string())?;
+
+    Ok(index)
+}
+
+// Writes a single note as a self-contained HTML file
+pub fn export_note(note: &SiteNote, output: &Path) -> Result<(), String> {
+    let body = render_markdown(&note.markdown, &HashMap::new(), note.base.as_deref(), &note.attachments);
+    let html = html_page(note_title(&note.name), &body, None, None);
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(output, html).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn note(name: &str, markdown: &str) -> SiteNote {
+        SiteNote { name: name.to_string(), markdown: markdown.to_string(), base: None, attachments: HashMap::new() }
+    }
+
+    #[test]
+    fn site_links_notes_and_escapes_names() {
+        let out = std::env::temp_dir().join(format!("neuro-site-{}", Uuid::new_v4()));
+        let section = SiteSection {
+            name: "Book".to_string(),
+            notes: vec![
+                note("<b>Bold</b>.md", "[next](Plain.md) [bad](javascript:alert(1))"),
+                note("Plain.md", "# Plain"),
+            ],
+        };
+
+        let index = export_site("Book & <Notes>", &[section], &out).unwrap();
+        let index = fs::read_to_string(index).unwrap();
+        assert!(index.contains("<h1>Book &amp; &lt;Notes&gt;</h1>"));
+        assert!(index.contains("&lt;b&gt;Bold&lt;/b&gt;"));
+        assert!(!index.contains("<b>Bold"));
+
+        let page = fs::read_to_string(out.join("b-bold-b.html")).unwrap();
+        assert!(page.contains("href=\"plain.html\""));
+        assert!(page.contains("href=\"#\""));
+
+        fs::remove_dir_all(out).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::command;
+use crate::db::client::now;
+use crate::llm::flashcards::{due_cards, generate_flashcards, study_day, Flashcard, Schedule, StudyCard, GRADES};
+use crate::llm::provider::LlmProvider;
+
+// deck file (inside the notebook's hidden folder) of a local notebook, skipped by exports
+const DECK_FILE: &str = ".neuro/flashcards.json";
+
+// Local deck, one person studies it so the schedules live alongside the cards
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct Deck {
+    cards: Vec<Flashcard>,
+    schedules: HashMap<String, Schedule>,
+}
+
+fn deck_file(notebook: &Path) -> PathBuf {
+    notebook.join(DECK_FILE)
+}
+
+// the notebook's deck, empty when it has none yet
+// any other read error is returned, so an unreadable deck is never saved over with an empty one
+fn load_deck(notebook: &Path) -> Result<Deck, String> {
+    match fs::read_to_string(deck_file(notebook)) {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Deck::default()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn save_deck(notebook: &Path, deck: &Deck) -> Result<(), String> {
+    let file = deck_file(notebook);
+    let json = serde_json::to_string_pretty(deck).map_err(|e| e.to_string())?;
+
+    fs::create_dir_all(file.parent().ok_or("Invalid notebook path")?).map_err(|e| e.to_string())?;
+    fs::write(file, json).map_err(|e| e.to_string())
+}
+
+// CREATE LOCAL FLASHCARDS (generated from a note into its notebook's deck), returns the new cards
+pub async fn create_local_flashcards(note: &Path, count: usize, provider: &dyn LlmProvider, model: Option<String>) -> Result<Vec<Flashcard>, String> {
+    let notebook = note.parent().ok_or("Invalid note path")?;
+    let name = note.file_name().ok_or("Invalid note path")?.to_string_lossy().into_owned();
+
+    let content = fs::read_to_string(note).map_err(|e| e.to_string())?;
+    if content.trim().is_empty() {
+        return Err("This note is empty".to_string());
+    }
+
+    let mut deck = load_deck(notebook)?;
+    let cards = generate_flashcards(&name, &content, count, &deck.cards, provider, model, now()).await?;
+
+    deck.cards.extend(cards.iter().cloned());
+    save_deck(notebook, &deck)?;
+
+    Ok(cards)
+}
+
+// every card in a local notebook's deck with its schedule
+#[command]
+pub fn local_deck(notebook: &str) -> Result<Vec<StudyCard>, String> {
+    let mut deck = load_deck(Path::new(notebook))?;
+
+    Ok(deck
+        .cards
+        .into_iter()
+        .map(|card| {
+            let schedule = deck.schedules.remove(&card.id).unwrap_or_default();
+            StudyCard { card, schedule }
+        })
+        .collect())
+}
+
+// cards of a local notebook due today or earlier, in the user's timezone
+#[command]
+pub fn local_due_cards(notebook: &str, utc_offset_minutes: Option<i32>, limit: Option<usize>) -> Result<Vec<StudyCard>, String> {
+    let deck = load_deck(Path::new(notebook))?;
+    let today = study_day(now(), utc_offset_minutes.unwrap_or(0));
+
+    Ok(due_cards(deck.cards, &deck.schedules, today, limit))
+}
+
+// records a review grade (0 to 5) for a local card, returns when it's next due
+#[command]
+pub fn review_local_card(notebook: &str, card_id: &str, grade: u8, utc_offset_minutes: Option<i32>) -> Result<Schedule, String> {
+    if !GRADES.contains(&grade) {
+        return Err(format!("Grade must be between {} and {}", GRADES.start(), GRADES.end()));
+    }
+
+    let notebook = Path::new(notebook);
+    let mut deck = load_deck(notebook)?;
+    if !deck.cards.iter().any(|card| card.id == card_id) {
+        return Err("Card not found".to_string());
+    }
+
+    let now = now();
+    let schedule = deck
+        .schedules
+        .get(card_id)
+        .cloned()
+        .unwrap_or_default()
+        .review(grade, study_day(now, utc_offset_minutes.unwrap_or(0)), now);
+
+    deck.schedules.insert(card_id.to_string(), schedule.clone());
+    save_deck(notebook, &deck)?;
+
+    Ok(schedule)
+}
+
+// deletes a card from a local deck
+#[command]
+pub fn delete_local_card(notebook: &str, card_id: &str) -> Result<(), String> {
+    let notebook = Path::new(notebook);
+    let mut deck = load_deck(notebook)?;
+
+    deck.cards.retain(|card| card.id != card_id);
+    deck.schedules.remove(card_id);
+
+    save_deck(notebook, &deck)
+}
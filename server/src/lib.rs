@@ -1,15 +1,55 @@
 mod files;
 mod llm;
 mod db;
+mod export;
+mod attachments;
+mod session;
+mod notify;
+mod threads;
+mod search;
+mod flashcards;
 
-use tauri::command;
+use tauri::{command, State};
 use simple_logger;
-use llm::ai::call_neuro;
-use db::{client::{get_user_id, get_user_session_data, delete_session}, 
-    models::User, 
-    ops::{create_user, create_vault, get_user, get_vaults,  get_vault_id, delete_vault, create_notebook, get_notebooks, get_notebook_id, delete_notebook, create_note, get_notes, get_note_id, read_note, save_note, delete_note}
+use llm::ai::{call_neuro, resolve_provider};
+use llm::provider::LlmProvider;
+use llm::actions::{self, ActionItem, Outline, Summary, TONES};
+use llm::flashcards::{Flashcard, Schedule, StudyCard, DEFAULT_CARDS};
+use llm::settings::{test_provider, LlmSettings, SettingsSummary, SettingsTest};
+use llm::stream::{stream_neuro, NeuroDone, NeuroStreams};
+use llm::conversation::{continue_conversation, Thread, ThreadSummary};
+use llm::rag::{ask_about_notes, GroundedAnswer};
+use llm::vectors::SearchHit;
+use search::search_local_notes;
+use db::embeddings::{index_saved_note, search_notes};
+use log::warn;
+use db::{client::get_user_profile, 
+    models::{Profile, Collaborator, DeviceSession, DeletionReport, LoginResponse, TotpEnrolment}, 
+    ops::{create_user, create_vault, get_user, verify_second_factor, LoginStep, get_vaults,  get_vault_id, delete_vault, create_notebook, get_notebooks, get_notebook_id, delete_notebook, create_note, get_notes, get_note_id, read_note, save_note, delete_note, get_notebook_contents, get_vault_contents, get_scope_notes}
 };
-use bcrypt::{hash, DEFAULT_COST};
+use db::account::{change_user_password, create_reset_token, disable_user_two_factor, preview_password_reset, reset_user_password};
+use db::two_factor::{begin_enrolment, confirm_enrolment};
+use db::deletion::delete_user_account;
+use db::flashcards::{create_flashcards, delete_card, get_deck, get_due_cards, review_card};
+use db::ai_settings::{clear_user_ai_settings, get_user_ai_settings, preview_user_ai_settings, update_user_ai_settings};
+use db::password::hash_password;
+use db::validation::validate_password;
+use db::profile::{change_user_email, get_user_preferences, update_user_forename, update_user_preferences};
+use db::attachments::{create_attachment, read_attachment, collect_attachments};
+use db::error::{DbError, DbResult};
+use db::crypto::UserKeys;
+use db::client::now;
+use db::threads::{continue_thread, delete_thread, get_thread, get_threads, truncate_thread};
+use db::sessions::{delete_session, get_sessions, revoke_session, revoke_other_sessions};
+use db::sharing::{invite_collaborator, get_collaborators, change_collaborator_role, revoke_collaborator};
+use session::Session;
+use notify::Notifications;
+use export::{remote_note, remote_section, site::{self, SiteSection}};
+use std::collections::HashMap;
+use std::path::Path;
+
+// semantic search results returned when the caller doesn't ask for a number
+const SEARCH_TOP_K: usize = 10;
 
 #[command]
 fn get_app_version(app: tauri::AppHandle) -> String {
@@ -24,166 +64,576 @@ fn logger(message: String) {
 }
 */
 
-// API call to OpenAI GPT model
+// provider and model for a logged in user, from their preferences and their own AI settings
+async fn user_provider(provider: Option<String>, model: Option<String>, keys: &UserKeys) -> DbResult<(Box<dyn LlmProvider>, Option<String>)> {
+    let preferences = get_user_preferences(keys).await?;
+    let settings = get_user_ai_settings(keys).await?;
+
+    Ok(resolve_provider(provider, model, &preferences, &settings)?)
+}
+
+// provider and model for commands that also work logged out (local vaults), which then only use the server's settings
+async fn session_provider(provider: Option<String>, model: Option<String>, session: &Session) -> Result<(Box<dyn LlmProvider>, Option<String>), String> {
+    match session.keys().await {
+        Ok(keys) => user_provider(provider, model, &keys).await.map_err(|e| e.to_string()),
+        Err(_) => resolve_provider(provider, model, &HashMap::new(), &LlmSettings::default()),
+    }
+}
+
+// API call to the chosen LLM provider, the request's provider and model win over the user's preferences
+#[command]
+async fn neuro(prompt: String, provider: Option<String>, model: Option<String>, session: State<'_, Session>) -> Result<String, DbError> {
+    let (provider, model) = session_provider(provider, model, &session).await?;
+    Ok(call_neuro(&prompt, provider.as_ref(), model).await?)
+}
+
+// streaming neuro, answers arrive as neuro-token events and end with a neuro-done event
+#[command]
+async fn neuro_stream(request_id: String, prompt: String, provider: Option<String>, model: Option<String>, app: tauri::AppHandle, session: State<'_, Session>, streams: State<'_, NeuroStreams>) -> Result<NeuroDone, DbError> {
+    let (provider, model) = session_provider(provider, model, &session).await?;
+    stream_neuro(app, &streams, request_id, prompt, provider, model).await
+}
+
+// asks a question in a synced note or notebook thread ("note:<id>" or "notebook:<id>"), a new thread without an id
+#[command]
+async fn continue_remote_thread(scope: String, thread_id: Option<String>, prompt: String, provider: Option<String>, model: Option<String>, session: State<'_, Session>) -> Result<Thread, DbError> {
+    let keys = session.keys().await?;
+    let (provider, model) = user_provider(provider, model, &keys).await?;
+    continue_thread(scope, thread_id, prompt, provider.as_ref(), model, &keys).await
+}
+
+// calls get threads query
+#[command]
+async fn list_remote_threads(scope: String, session: State<'_, Session>) -> Result<Vec<ThreadSummary>, DbError> {
+    get_threads(scope, &session.keys().await?).await
+}
+
+// calls get thread query
+#[command]
+async fn read_remote_thread(id: String, session: State<'_, Session>) -> Result<Thread, DbError> {
+    get_thread(id, &session.keys().await?).await
+}
+
+// calls truncate thread query
+#[command]
+async fn truncate_remote_thread(id: String, max_tokens: Option<usize>, session: State<'_, Session>) -> Result<usize, DbError> {
+    truncate_thread(id, max_tokens, &session.keys().await?).await
+}
+
+// calls delete thread query
+#[command]
+async fn delete_remote_thread(id: String, session: State<'_, Session>) -> Result<(), DbError> {
+    delete_thread(id, &session.keys().await?).await
+}
+
+// asks a question in a local note or notebook thread, stored in the notebook's hidden .neuro folder
+#[command]
+async fn continue_local_thread(scope: String, thread_id: Option<String>, prompt: String, provider: Option<String>, model: Option<String>, session: State<'_, Session>) -> Result<Thread, String> {
+    let (provider, model) = session_provider(provider, model, &session).await?;
+
+    let mut thread = match thread_id {
+        Some(id) => threads::load_thread(&scope, &id)?,
+        None => threads::new_thread(&scope, &prompt, now())?,
+    };
+
+    continue_conversation(&mut thread, prompt, provider.as_ref(), model, now()).await?;
+    threads::save_thread(&scope, &thread)?;
+
+    Ok(thread)
+}
+
+// answers a question from the notes in a scope ("note:<id>", "notebook:<id>" or "vault:<id>"), with citations
+#[command]
+async fn ask_neuro_about(scope: String, question: String, provider: Option<String>, model: Option<String>, session: State<'_, Session>) -> Result<GroundedAnswer, DbError> {
+    let keys = session.keys().await?;
+    let (provider, model) = user_provider(provider, model, &keys).await?;
+    let notes = get_scope_notes(scope, &keys).await?;
+
+    Ok(ask_about_notes(&question, notes, provider.as_ref(), model).await?)
+}
+
+// closest note chunks to a query within a synced note, notebook or vault ("note:<id>", "notebook:<id>" or "vault:<id>")
+#[command]
+async fn semantic_search(scope: String, query: String, top_k: Option<usize>, provider: Option<String>, session: State<'_, Session>) -> Result<Vec<SearchHit>, DbError> {
+    let keys = session.keys().await?;
+    let (provider, _) = user_provider(provider, None, &keys).await?;
+
+    search_notes(scope, query, top_k.unwrap_or(SEARCH_TOP_K), provider.as_ref(), &keys).await
+}
+
+// closest note chunks to a query within a local note, notebook or vault folder
+#[command]
+async fn semantic_search_local(scope: String, query: String, top_k: Option<usize>, provider: Option<String>, session: State<'_, Session>) -> Result<Vec<SearchHit>, String> {
+    let (provider, _) = session_provider(provider, None, &session).await?;
+    search_local_notes(Path::new(&scope), query, top_k.unwrap_or(SEARCH_TOP_K), provider.as_ref()).await
+}
+
+// refreshes a local note's embeddings after it's saved, returns how many chunks were embedded
+#[command]
+async fn index_local_note(path: String, provider: Option<String>, session: State<'_, Session>) -> Result<usize, String> {
+    let (provider, _) = session_provider(provider, None, &session).await?;
+    search::index_local_note(Path::new(&path), provider.as_ref()).await
+}
+
+// the text an AI action works on (the editor's, so unsaved and local notes work too)
+fn action_text(text: &str) -> DbResult<&str> {
+    match text.trim() {
+        "" => Err(DbError::Invalid("There's no text to work with".to_string())),
+        text => Ok(text),
+    }
+}
+
+// summary and key points of a note
+#[command]
+async fn summarise_note(text: String, provider: Option<String>, model: Option<String>, session: State<'_, Session>) -> Result<Summary, DbError> {
+    let (provider, model) = session_provider(provider, model, &session).await?;
+    Ok(actions::summarise(action_text(&text)?, provider.as_ref(), model).await?)
+}
+
+// title and sections of a note
+#[command]
+async fn outline_note(text: String, provider: Option<String>, model: Option<String>, session: State<'_, Session>) -> Result<Outline, DbError> {
+    let (provider, model) = session_provider(provider, model, &session).await?;
+    Ok(actions::outline(action_text(&text)?, provider.as_ref(), model).await?)
+}
+
+// explains a selection, the rest of the note is used as context
+#[command]
+async fn explain_selection(selection: String, text: String, provider: Option<String>, model: Option<String>, session: State<'_, Session>) -> Result<String, DbError> {
+    let (provider, model) = session_provider(provider, model, &session).await?;
+    Ok(actions::explain(action_text(&selection)?, &text, provider.as_ref(), model).await?)
+}
+
+// rewrites text in one of the supported tones
+#[command]
+async fn rewrite_text(text: String, tone: String, provider: Option<String>, model: Option<String>, session: State<'_, Session>) -> Result<String, DbError> {
+    if !TONES.contains(&tone.as_str()) {
+        return Err(DbError::Invalid(format!("Tone must be one of: {}", TONES.join(", "))));
+    }
+
+    let (provider, model) = session_provider(provider, model, &session).await?;
+    Ok(actions::rewrite(action_text(&text)?, &tone, provider.as_ref(), model).await?)
+}
+
+// the next paragraph or two of a note
+#[command]
+async fn continue_writing(text: String, provider: Option<String>, model: Option<String>, session: State<'_, Session>) -> Result<String, DbError> {
+    let (provider, model) = session_provider(provider, model, &session).await?;
+    Ok(actions::continue_writing(action_text(&text)?, provider.as_ref(), model).await?)
+}
+
+// tasks in a note, with owner, due date and priority when it says
+#[command]
+async fn extract_action_items(text: String, provider: Option<String>, model: Option<String>, session: State<'_, Session>) -> Result<Vec<ActionItem>, DbError> {
+    let (provider, model) = session_provider(provider, model, &session).await?;
+    Ok(actions::action_items(action_text(&text)?, provider.as_ref(), model).await?)
+}
+
+// generates flashcards from a synced note into its notebook's deck
+#[command]
+async fn generate_remote_flashcards(note_id: String, count: Option<usize>, provider: Option<String>, model: Option<String>, session: State<'_, Session>) -> Result<Vec<Flashcard>, DbError> {
+    let keys = session.keys().await?;
+    let (provider, model) = user_provider(provider, model, &keys).await?;
+    create_flashcards(note_id, count.unwrap_or(DEFAULT_CARDS), provider.as_ref(), model, &keys).await
+}
+
+// calls get deck query
+#[command]
+async fn remote_deck(notebook_id: String, session: State<'_, Session>) -> Result<Vec<StudyCard>, DbError> {
+    get_deck(notebook_id, &session.keys().await?).await
+}
+
+// calls get due cards query, "today" ends at the user's midnight
+#[command]
+async fn remote_due_cards(notebook_id: String, utc_offset_minutes: Option<i32>, limit: Option<usize>, session: State<'_, Session>) -> Result<Vec<StudyCard>, DbError> {
+    get_due_cards(notebook_id, utc_offset_minutes.unwrap_or(0), limit, &session.keys().await?).await
+}
+
+// calls review card query with a grade from 0 (forgot) to 5 (perfect)
 #[command]
-async fn neuro(prompt: String) -> Result<String, String> {
-    call_neuro(&prompt)
-    .await
-    .map_err(|e| e.to_string())
+async fn review_remote_card(notebook_id: String, card_id: String, grade: u8, utc_offset_minutes: Option<i32>, session: State<'_, Session>) -> Result<Schedule, DbError> {
+    review_card(notebook_id, card_id, grade, utc_offset_minutes.unwrap_or(0), &session.keys().await?).await
+}
+
+// calls delete card query
+#[command]
+async fn delete_remote_card(notebook_id: String, card_id: String, session: State<'_, Session>) -> Result<(), DbError> {
+    delete_card(notebook_id, card_id, &session.keys().await?).await
+}
+
+// generates flashcards from a local note into its notebook's hidden deck file
+#[command]
+async fn generate_local_flashcards(path: String, count: Option<usize>, provider: Option<String>, model: Option<String>, session: State<'_, Session>) -> Result<Vec<Flashcard>, String> {
+    let (provider, model) = session_provider(provider, model, &session).await?;
+    flashcards::create_local_flashcards(Path::new(&path), count.unwrap_or(DEFAULT_CARDS), provider.as_ref(), model).await
+}
+
+// aborts a streaming neuro request, false if it already finished
+#[command]
+fn cancel_neuro(request_id: String, streams: State<'_, NeuroStreams>) -> Result<bool, String> {
+    streams.cancel(&request_id)
 }
 
 // calls create user query
 #[command]
-async fn add_user(forename: String, email: String, password: String) -> Result<String, String> {
-    let hashed_pass = hash(password, DEFAULT_COST).map_err(|e| e.to_string())?; // encrypts user password before injecting 
-    create_user(forename, email, hashed_pass)
-    .await
-    .map_err(|e| e.to_string())
+async fn add_user(forename: String, email: String, password: String) -> Result<String, DbError> {
+    validate_password(&password)?;
+    let hashed_pass = hash_password(&password)?; // hashes user password (Argon2id) before injecting 
+    create_user(forename, email, hashed_pass).await
 }
 
-// verifies user account for login
+// verifies user account for login, then holds the session and unlocked keys in state
+// accounts with two-factor enabled wait for verify_two_factor before a session exists
 #[command]
-async fn verify_user(email: String, password: String) -> Result<String, String> {
-    get_user(email, password)
-        .await
-        .map_err(|e| e.to_string())
+async fn verify_user(email: String, password: String, device: Option<String>, session: State<'_, Session>) -> Result<LoginResponse, DbError> {
+    match get_user(email.clone(), password, device.clone()).await? {
+        LoginStep::Authenticated(token, keys) => {
+            session.start(token.clone(), keys)?;
+            Ok(LoginResponse::Authenticated { token })
+        }
+        LoginStep::TwoFactor(keys) => {
+            session.await_two_factor(email, device, keys)?;
+            Ok(LoginResponse::TwoFactorRequired)
+        }
+    }
 }
 
-// calls get session data query
+// second login step, takes an authenticator or recovery code
 #[command]
-async fn get_user_data(session_token: String) -> Result<User, String> {
-    get_user_session_data(session_token)
-        .await
-        .map_err(|e| e.to_string())
+async fn verify_two_factor(code: String, session: State<'_, Session>) -> Result<LoginResponse, DbError> {
+    let pending = session.pending()?;
+    let token = verify_second_factor(pending.email, pending.device, code, &pending.keys).await?;
+
+    session.start(token.clone(), pending.keys)?;
+    Ok(LoginResponse::Authenticated { token })
 }
 
-// calls get user ID query
+// starts two-factor enrolment, returns the secret and otpauth URI
 #[command]
-async fn get_id(email: String) -> Result<String, String> {
-    get_user_id(email)
-        .await
-        .map_err(|e| e.to_string())
+async fn enrol_two_factor(session: State<'_, Session>) -> Result<TotpEnrolment, DbError> {
+    begin_enrolment(&session.keys().await?).await
+}
+
+// confirms enrolment with a first code, returns the recovery codes
+#[command]
+async fn confirm_two_factor(code: String, session: State<'_, Session>) -> Result<Vec<String>, DbError> {
+    confirm_enrolment(code, &session.keys().await?).await
+}
+
+// turns two-factor off, needs the password and a code
+#[command]
+async fn disable_two_factor(password: String, code: String, session: State<'_, Session>) -> Result<(), DbError> {
+    disable_user_two_factor(password, code, &session.keys().await?).await
+}
+
+// calls get profile query for the logged in user (never includes the password hash)
+#[command]
+async fn get_user_data(session: State<'_, Session>) -> Result<Profile, DbError> {
+    get_user_profile(session.current().await?.token).await
+}
+
+// updates the logged in user's forename
+#[command]
+async fn update_forename(forename: String, session: State<'_, Session>) -> Result<(), DbError> {
+    update_user_forename(forename, &session.keys().await?).await
+}
+
+// moves the account to a new email, the password is checked again
+#[command]
+async fn change_email(email: String, password: String, session: State<'_, Session>) -> Result<(), DbError> {
+    change_user_email(email, password, &session.keys().await?).await
+}
+
+// sets or clears (with an empty value) preferences, returns all of them
+#[command]
+async fn update_preferences(preferences: HashMap<String, String>, session: State<'_, Session>) -> Result<HashMap<String, String>, DbError> {
+    update_user_preferences(preferences, &session.keys().await?).await
+}
+
+// the user's own AI settings, the API key only by its last characters
+#[command]
+async fn get_ai_settings(session: State<'_, Session>) -> Result<SettingsSummary, DbError> {
+    Ok(get_user_ai_settings(&session.keys().await?).await?.summary())
+}
+
+// sets or clears (with an empty value) the user's own API key, server URL, temperature and max tokens
+#[command]
+async fn set_ai_settings(settings: HashMap<String, String>, session: State<'_, Session>) -> Result<SettingsSummary, DbError> {
+    update_user_ai_settings(settings, &session.keys().await?).await
+}
+
+// sends a short request with the stored settings, or with unsaved changes to them
+#[command]
+async fn test_ai_settings(settings: Option<HashMap<String, String>>, provider: Option<String>, model: Option<String>, session: State<'_, Session>) -> Result<SettingsTest, DbError> {
+    let keys = session.keys().await?;
+    let settings = preview_user_ai_settings(settings.unwrap_or_default(), &keys).await?;
+    let (provider, model) = resolve_provider(provider, model, &get_user_preferences(&keys).await?, &settings)?;
+
+    Ok(test_provider(provider.as_ref(), model).await?)
+}
+
+// forgets the user's own AI settings, the server's are used again
+#[command]
+async fn clear_ai_settings(session: State<'_, Session>) -> Result<(), DbError> {
+    clear_user_ai_settings(&session.keys().await?).await
 }
 
 // calls create vault query
 #[command]
-async fn add_vault(name: String, id: String) -> Result<String, String> {
-    create_vault(name, id)
-        .await
-        .map_err(|e| e.to_string())
+async fn add_vault(name: String, session: State<'_, Session>) -> Result<String, DbError> {
+    create_vault(name, &session.keys().await?).await
 }
 
 // calls get vault name query
 #[command]
-async fn get_vault_names(id: String) -> Result<Vec<String>, String> {
-    get_vaults(id)
-        .await
-        .map_err(|e| e.to_string())
+async fn get_vault_names(session: State<'_, Session>) -> Result<Vec<String>, DbError> {
+    get_vaults(&session.keys().await?).await
 }
 
 // calls get vault ID query
 #[command]
-async fn vault_id(name: String) -> Result<String, String> {
-    get_vault_id(name)
-        .await
-        .map_err(|e| e.to_string())
+async fn vault_id(name: String, session: State<'_, Session>) -> Result<String, DbError> {
+    get_vault_id(name, &session.keys().await?).await
 }
 
 // calls delete vault query
 #[command]
-async fn drop_vault(vid: String, name: String, uid: String) -> Result<(), String> {
-    delete_vault(vid, name, uid)
-        .await
-        .map_err(|e| e.to_string())
+async fn drop_vault(vid: String, session: State<'_, Session>) -> Result<(), DbError> {
+    delete_vault(vid, &session.keys().await?).await
 }
 
-// calls logout query
+// calls invite collaborator query
 #[command]
-async fn logout(token: String, id: String) -> Result<bool, String> {
-    delete_session(token, id)
-        .await
-        .map_err(|e| e.to_string())
+async fn share_vault(vid: String, email: String, role: String, session: State<'_, Session>) -> Result<(), DbError> {
+    invite_collaborator(vid, email, role, &session.keys().await?).await
+}
+
+// calls get collaborators query
+#[command]
+async fn collaborators(vid: String, session: State<'_, Session>) -> Result<Vec<Collaborator>, DbError> {
+    get_collaborators(vid, &session.keys().await?).await
+}
+
+// calls change collaborator role query
+#[command]
+async fn set_collaborator_role(vid: String, uid: String, role: String, session: State<'_, Session>) -> Result<(), DbError> {
+    change_collaborator_role(vid, uid, role, &session.keys().await?).await
+}
+
+// calls revoke collaborator query
+#[command]
+async fn remove_collaborator(vid: String, uid: String, session: State<'_, Session>) -> Result<(), DbError> {
+    revoke_collaborator(vid, uid, &session.keys().await?).await
+}
+
+// changes the password of the logged in user, other devices are logged out
+#[command]
+async fn change_password(current: String, new: String, session: State<'_, Session>) -> Result<(), DbError> {
+    let active = session.current().await?;
+    change_user_password(current, new, &active.token, &active.keys).await
+}
+
+// deletes the account and everything it owns, dry_run only reports what would go
+// an interrupted deletion is finished by calling this again (logging in again if needed)
+#[command]
+async fn delete_account(password: String, dry_run: Option<bool>, session: State<'_, Session>) -> Result<DeletionReport, DbError> {
+    let dry_run = dry_run.unwrap_or(false);
+    let report = delete_user_account(password, dry_run, &session.keys().await?).await?;
+
+    if !dry_run {
+        session.end()?;
+    }
+    Ok(report)
+}
+
+// sends a password reset token through the configured notifier
+#[command]
+async fn forgot_password(email: String, notifications: State<'_, Notifications>) -> Result<(), DbError> {
+    create_reset_token(email, notifications.0.as_ref()).await
+}
+
+// lists what a password reset would delete (vaults that can't be opened without the old password)
+#[command]
+async fn preview_reset(token: String) -> Result<DeletionReport, DbError> {
+    preview_password_reset(token).await
+}
+
+// sets a new password from a reset token, confirm has to be set when the preview found vaults to delete
+// and accounts with two-factor enabled need one of their recovery codes
+#[command]
+async fn reset_password(token: String, password: String, recovery_code: Option<String>, confirm: Option<bool>) -> Result<DeletionReport, DbError> {
+    reset_user_password(token, password, recovery_code, confirm.unwrap_or(false)).await
+}
+
+// rotates the session token
+#[command]
+async fn refresh_session(session: State<'_, Session>) -> Result<String, DbError> {
+    session.refresh().await
+}
+
+// calls get sessions query (every device the user is logged in on)
+#[command]
+async fn list_sessions(session: State<'_, Session>) -> Result<Vec<DeviceSession>, DbError> {
+    get_sessions(session.current().await?.token).await
+}
+
+// calls revoke session query, revoking the current session logs this device out
+#[command]
+async fn revoke_device_session(id: String, session: State<'_, Session>) -> Result<(), DbError> {
+    if revoke_session(session.current().await?.token, id).await? {
+        session.end()?;
+    }
+    Ok(())
+}
+
+// calls revoke other sessions query
+#[command]
+async fn revoke_other_device_sessions(session: State<'_, Session>) -> Result<usize, DbError> {
+    revoke_other_sessions(session.current().await?.token).await
+}
+
+// calls logout query and forgets the session and encryption keys
+#[command]
+async fn logout(session: State<'_, Session>) -> Result<bool, DbError> {
+    match session.end()? {
+        Some(active) => delete_session(active.token).await,
+        None => Ok(true),
+    }
 }
 
 
 // calls create notebook query
 #[command]
-async fn add_notebook(name: String, id: String) -> Result<String, String> {
-    create_notebook(name, id)
-        .await
-        .map_err(|e| e.to_string())
+async fn add_notebook(name: String, vid: String, session: State<'_, Session>) -> Result<String, DbError> {
+    create_notebook(name, vid, &session.keys().await?).await
 }
 
 // calls get notebook names query
 #[command]
-async fn get_notebook_names(id: String) -> Result<Vec<String>, String> {
-    get_notebooks(id)
-        .await
-        .map_err(|e| e.to_string())
+async fn get_notebook_names(vid: String, session: State<'_, Session>) -> Result<Vec<String>, DbError> {
+    get_notebooks(vid, &session.keys().await?).await
 }
 
 // calls get notebook ID query
 #[command]
-async fn notebook_id(name: String) -> Result<String, String> {
-    get_notebook_id(name)
-        .await
-        .map_err(|e| e.to_string())
+async fn notebook_id(name: String, vid: String, session: State<'_, Session>) -> Result<String, DbError> {
+    get_notebook_id(name, vid, &session.keys().await?).await
 }
 
 // calls delete notebook query
 #[command]
-async fn drop_notebook(nid: String, name: String, vid: String) -> Result<(), String> {
-    delete_notebook(nid, name, vid)
-        .await
-        .map_err(|e| e.to_string())
+async fn drop_notebook(nid: String, session: State<'_, Session>) -> Result<(), DbError> {
+    delete_notebook(nid, &session.keys().await?).await
 }
 
 // calls add note query
 #[command]
-async fn add_note(name: String, nid: String) -> Result<String, String> {
-    create_note(name, nid)
-        .await
-        .map_err(|e| e.to_string())
+async fn add_note(name: String, nid: String, session: State<'_, Session>) -> Result<String, DbError> {
+    create_note(name, nid, &session.keys().await?).await
 }
 
 // calls get note names query
 #[command]
-async fn get_note_names(id: String) -> Result<Vec<String>, String> {
-    get_notes(id)
-        .await
-        .map_err(|e| e.to_string())
+async fn get_note_names(nid: String, session: State<'_, Session>) -> Result<Vec<String>, DbError> {
+    get_notes(nid, &session.keys().await?).await
 }
 
 // calls get note ID query
 #[command]
-async fn note_id(name: String) -> Result<String, String> {
-    get_note_id(name)
-        .await
-        .map_err(|e| e.to_string())
+async fn note_id(name: String, nid: String, session: State<'_, Session>) -> Result<String, DbError> {
+    get_note_id(name, nid, &session.keys().await?).await
 }
 
 // calls read note query
 #[command]
-async fn read_remote_note(id: String) -> Result<String, String> {
-    read_note(id)
-        .await
-        .map_err(|e| e.to_string())
+async fn read_remote_note(id: String, session: State<'_, Session>) -> Result<String, DbError> {
+    read_note(id, &session.keys().await?).await
 }
 
-// calls save note query
+// calls save note query, then refreshes the note's embeddings in the background unless the user turned them off
 #[command]
-async fn save_remote_note(id: String, content: String) -> Result<String, String> {
-    save_note(id, content)
-        .await
-        .map_err(|e| e.to_string())
+async fn save_remote_note(id: String, content: String, session: State<'_, Session>) -> Result<String, DbError> {
+    let keys = session.keys().await?;
+    let saved = save_note(id.clone(), content, &keys).await?;
+
+    let preferences = get_user_preferences(&keys).await?;
+    if preferences.get("semantic_index").is_none_or(|value| value != "off") {
+        // saving never waits on the provider (or fails without one), a failed refresh is caught up on the next search
+        match resolve_provider(None, None, &preferences, &get_user_ai_settings(&keys).await?) {
+            Ok((provider, _)) => {
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = index_saved_note(id, &keys, provider.as_ref()).await {
+                        warn!("Could not index note: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("Could not index note: {}", e),
+        }
+    }
+
+    Ok(saved)
 }
 
 // calls delete note query
 #[command]
-async fn delete_remote_note(id: String, name: String, nid: String) -> Result<(), String> {
-    delete_note(id, name, nid)
+async fn delete_remote_note(id: String, session: State<'_, Session>) -> Result<(), DbError> {
+    delete_note(id, &session.keys().await?).await
+}
+
+// calls create attachment query
+#[command]
+async fn add_remote_attachment(id: String, name: String, data: Vec<u8>, session: State<'_, Session>) -> Result<String, DbError> {
+    create_attachment(id, name, data, &session.keys().await?).await
+}
+
+// calls read attachment query
+#[command]
+async fn read_remote_attachment(reference: String, session: State<'_, Session>) -> Result<Vec<u8>, DbError> {
+    read_attachment(reference, &session.keys().await?)
         .await
-        .map_err(|e| e.to_string())
+        .map(|(_, data)| data)
+}
+
+// calls attachment garbage collection query
+#[command]
+async fn collect_remote_attachments(id: String, session: State<'_, Session>) -> Result<Vec<String>, DbError> {
+    collect_attachments(id, &session.keys().await?).await
+}
+
+// renders a remote note to a standalone HTML file
+#[command]
+async fn render_remote_note(id: String, name: String, output: String, session: State<'_, Session>) -> Result<(), DbError> {
+    let keys = session.keys().await?;
+    let markdown = read_note(id, &keys).await?;
+
+    let note = remote_note(name, markdown, &keys).await?;
+    Ok(site::export_note(&note, Path::new(&output))?)
+}
+
+// exports a remote notebook as a static site
+#[command]
+async fn export_remote_notebook(id: String, name: String, output: String, session: State<'_, Session>) -> Result<String, DbError> {
+    let keys = session.keys().await?;
+    let notes = get_notebook_contents(id, &keys).await?;
+
+    let section = remote_section(name.clone(), notes, &keys).await?;
+    let index = site::export_site(&name, &[section], Path::new(&output))?;
+    Ok(index.to_string_lossy().into_owned())
+}
+
+// exports a remote vault as a static site
+#[command]
+async fn export_remote_vault(id: String, name: String, output: String, session: State<'_, Session>) -> Result<String, DbError> {
+    let keys = session.keys().await?;
+    let notebooks = get_vault_contents(id, &keys).await?;
+
+    let mut sections: Vec<SiteSection> = Vec::new();
+    for (notebook, notes) in notebooks {
+        sections.push(remote_section(notebook, notes, &keys).await?);
+    }
+
+    let index = site::export_site(&name, &sections, Path::new(&output))?;
+    Ok(index.to_string_lossy().into_owned())
 }
 
 #[command]
@@ -198,8 +648,13 @@ pub fn run() {
 
     println!("{}", get_environment_variable("DATABASE_URL"));
 
+    let notifications = Notifications::from_env().expect("Could not set up password reset notifications");
+
     // Tauri API method calls
     tauri::Builder::default()
+        .manage(Session::default())
+        .manage(notifications)
+        .manage(NeuroStreams::default())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
@@ -207,12 +662,35 @@ pub fn run() {
             get_app_version, 
             add_user,
             verify_user,
+            verify_two_factor,
+            enrol_two_factor,
+            confirm_two_factor,
+            disable_two_factor,
             get_user_data,
-            get_id,
+            update_forename,
+            change_email,
+            update_preferences,
+            get_ai_settings,
+            set_ai_settings,
+            test_ai_settings,
+            clear_ai_settings,
             add_vault,
             get_vault_names,
             vault_id,
             drop_vault,
+            share_vault,
+            collaborators,
+            set_collaborator_role,
+            remove_collaborator,
+            change_password,
+            delete_account,
+            forgot_password,
+            preview_reset,
+            reset_password,
+            refresh_session,
+            list_sessions,
+            revoke_device_session,
+            revoke_other_device_sessions,
             logout,
             add_notebook,
             get_notebook_names,
@@ -224,6 +702,12 @@ pub fn run() {
             read_remote_note,
             save_remote_note,
             delete_remote_note,
+            add_remote_attachment,
+            read_remote_attachment,
+            collect_remote_attachments,
+            render_remote_note,
+            export_remote_notebook,
+            export_remote_vault,
             files::read_file,
             files::save_file,
             files::delete_file,
@@ -231,7 +715,45 @@ pub fn run() {
             files::delete_folder,
             files::create_folder,
             files::get_first_file,
-            neuro
+            attachments::save_attachment,
+            attachments::read_attachment,
+            attachments::collect_attachments,
+            export::render_note,
+            export::export_notebook,
+            export::export_vault,
+            neuro,
+            neuro_stream,
+            cancel_neuro,
+            ask_neuro_about,
+            summarise_note,
+            outline_note,
+            explain_selection,
+            rewrite_text,
+            continue_writing,
+            extract_action_items,
+            semantic_search,
+            semantic_search_local,
+            index_local_note,
+            continue_remote_thread,
+            list_remote_threads,
+            read_remote_thread,
+            truncate_remote_thread,
+            delete_remote_thread,
+            continue_local_thread,
+            threads::list_threads,
+            threads::read_thread,
+            threads::truncate_thread,
+            threads::delete_thread,
+            generate_remote_flashcards,
+            remote_deck,
+            remote_due_cards,
+            review_remote_card,
+            delete_remote_card,
+            generate_local_flashcards,
+            flashcards::local_deck,
+            flashcards::local_due_cards,
+            flashcards::review_local_card,
+            flashcards::delete_local_card
             ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,241 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use super::error::LlmError;
+use super::provider::{ChatMessage, ChatRequest, LlmProvider};
+use super::schema::validate;
+
+// tones a note can be rewritten in
+pub const TONES: [&str; 6] = ["formal", "casual", "concise", "friendly", "academic", "simple"];
+
+// a structured answer that doesn't fit its schema is sent back once to be fixed
+const REPAIR_ATTEMPTS: usize = 1;
+
+const SYSTEM_PROMPT: &str = "You help a student work on their study notes. \
+Follow the instruction exactly and only use what the notes say, never invent facts.";
+
+// prompt templates, {text}, {context} and {tone} are filled in per request
+const SUMMARISE: &str = "Summarise the note below in one short paragraph, then list its key points \
+(at most 7, each one sentence).\n\nNote:\n{text}";
+const OUTLINE: &str = "Write a hierarchical outline of the note below: a title, then its sections in order, \
+each with a heading and its main points as short phrases.\n\nNote:\n{text}";
+const EXPLAIN: &str = "Explain the selected passage so a student meeting it for the first time understands it. \
+Define any terms it uses and give a short example if it helps. Use the rest of the note only as context. \
+Respond in markdown, at most 3 paragraphs.\n\nNote:\n{context}\n\nSelected passage:\n{text}";
+const REWRITE: &str = "Rewrite the text below in a {tone} tone. Keep its meaning, facts and markdown structure. \
+Respond with only the rewritten text.\n\nText:\n{text}";
+const CONTINUE: &str = "Continue writing the note below from exactly where it stops, in the same voice, \
+format and language. Add one or two paragraphs at most and respond with only the new text, \
+without repeating what is already there.\n\nNote:\n{text}";
+const ACTION_ITEMS: &str = "List every action item in the note below: tasks, to-dos and follow-ups someone has \
+to do. Give the owner and due date only when the note states them, and a priority when the note makes it clear. \
+Return an empty list if there are none.\n\nNote:\n{text}";
+
+// Summary of a note
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Summary {
+    pub summary: String,
+    pub key_points: Vec<String>,
+}
+
+// Outline of a note, one level of sections
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Outline {
+    pub title: String,
+    pub sections: Vec<OutlineSection>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutlineSection {
+    pub heading: String,
+    pub points: Vec<String>,
+}
+
+// Task found in a note, owner, due date and priority only when the note says
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionItem {
+    pub task: String,
+    pub owner: Option<String>,
+    pub due: Option<String>,
+    pub priority: Option<String>, // "high", "medium" or "low"
+}
+
+#[derive(Deserialize)]
+struct ActionItems {
+    items: Vec<ActionItem>,
+}
+
+fn summary_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "summary": { "type": "string", "minLength": 1 },
+            "key_points": { "type": "array", "items": { "type": "string", "minLength": 1 }, "maxItems": 7 },
+        },
+        "required": ["summary", "key_points"],
+        "additionalProperties": false,
+    })
+}
+
+fn outline_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "title": { "type": "string", "minLength": 1 },
+            "sections": {
+                "type": "array",
+                "minItems": 1,
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "heading": { "type": "string", "minLength": 1 },
+                        "points": { "type": "array", "items": { "type": "string", "minLength": 1 } },
+                    },
+                    "required": ["heading", "points"],
+                    "additionalProperties": false,
+                },
+            },
+        },
+        "required": ["title", "sections"],
+        "additionalProperties": false,
+    })
+}
+
+fn action_items_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "items": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "task": { "type": "string", "minLength": 1 },
+                        "owner": { "type": ["string", "null"] },
+                        "due": { "type": ["string", "null"] },
+                        "priority": { "type": ["string", "null"], "enum": ["high", "medium", "low", null] },
+                    },
+                    "required": ["task", "owner", "due", "priority"],
+                    "additionalProperties": false,
+                },
+            },
+        },
+        "required": ["items"],
+        "additionalProperties": false,
+    })
+}
+
+// fills a template's placeholders in one pass, so braces in the note itself are left alone
+pub fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut prompt = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        prompt.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let placeholder = after.find('}').and_then(|end| {
+            let (_, value) = values.iter().find(|(name, _)| *name == &after[..end])?;
+            Some((value, end))
+        });
+        match placeholder {
+            Some((value, end)) => {
+                prompt.push_str(value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                prompt.push('{');
+                rest = after;
+            }
+        }
+    }
+
+    prompt.push_str(rest);
+    prompt
+}
+
+// models sometimes wrap JSON in a code fence even when asked not to
+fn strip_fence(answer: &str) -> &str {
+    let answer = answer.trim();
+    match answer.strip_prefix("```") {
+        Some(fenced) => fenced
+            .trim_start_matches("json")
+            .trim_end_matches("```")
+            .trim(),
+        None => answer,
+    }
+}
+
+// parses an answer and checks it against the schema
+fn parse<T: DeserializeOwned>(answer: &str, schema: &Value) -> Result<T, String> {
+    let value: Value = serde_json::from_str(strip_fence(answer)).map_err(|e| format!("not valid JSON: {}", e))?;
+    validate(&value, schema)?;
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+// free text answer to a filled template
+async fn text(provider: &dyn LlmProvider, model: Option<String>, prompt: String) -> Result<String, LlmError> {
+    let request = ChatRequest {
+        model,
+        messages: vec![ChatMessage::system(SYSTEM_PROMPT), ChatMessage::user(prompt)],
+        schema: None,
+    };
+
+    Ok(provider.complete(&request).await?.trim().to_string())
+}
+
+// structured answer to a filled template, validated against the schema
+pub async fn structured<T: DeserializeOwned>(provider: &dyn LlmProvider, model: Option<String>, prompt: String, schema: Value) -> Result<T, LlmError> {
+    let system = format!(
+        "{}\nReply with only a JSON object following this JSON schema, without code fences or other text:\n{}",
+        SYSTEM_PROMPT, schema
+    );
+    let mut messages = vec![ChatMessage::system(system), ChatMessage::user(prompt)];
+    let mut repairs = 0;
+
+    loop {
+        let request = ChatRequest { model: model.clone(), messages: messages.clone(), schema: Some(schema.clone()) };
+        let answer = provider.complete(&request).await?;
+
+        match parse(&answer, &schema) {
+            Ok(result) => return Ok(result),
+            Err(e) if repairs < REPAIR_ATTEMPTS => {
+                repairs += 1;
+                messages.push(ChatMessage::assistant(answer));
+                messages.push(ChatMessage::user(format!("That answer doesn't follow the schema ({}). Reply again with only the corrected JSON.", e)));
+            }
+            Err(e) => return Err(LlmError::Malformed(e)),
+        }
+    }
+}
+
+// SUMMARISE
+pub async fn summarise(note: &str, provider: &dyn LlmProvider, model: Option<String>) -> Result<Summary, LlmError> {
+    structured(provider, model, fill(SUMMARISE, &[("text", note)]), summary_schema()).await
+}
+
+// OUTLINE
+pub async fn outline(note: &str, provider: &dyn LlmProvider, model: Option<String>) -> Result<Outline, LlmError> {
+    structured(provider, model, fill(OUTLINE, &[("text", note)]), outline_schema()).await
+}
+
+// EXPLAIN (a selection, with the note around it as context)
+pub async fn explain(selection: &str, note: &str, provider: &dyn LlmProvider, model: Option<String>) -> Result<String, LlmError> {
+    text(provider, model, fill(EXPLAIN, &[("text", selection), ("context", note)])).await
+}
+
+// REWRITE (in one of TONES, checked by the caller)
+pub async fn rewrite(passage: &str, tone: &str, provider: &dyn LlmProvider, model: Option<String>) -> Result<String, LlmError> {
+    text(provider, model, fill(REWRITE, &[("tone", tone), ("text", passage)])).await
+}
+
+// CONTINUE WRITING
+pub async fn continue_writing(note: &str, provider: &dyn LlmProvider, model: Option<String>) -> Result<String, LlmError> {
+    text(provider, model, fill(CONTINUE, &[("text", note)])).await
+}
+
+// ACTION ITEMS
+pub async fn action_items(note: &str, provider: &dyn LlmProvider, model: Option<String>) -> Result<Vec<ActionItem>, LlmError> {
+    let items: ActionItems = structured(provider, model, fill(ACTION_ITEMS, &[("text", note)]), action_items_schema()).await?;
+    Ok(items.items)
+}
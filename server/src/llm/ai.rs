@@ -1,40 +1,64 @@
-use reqwest::Client;
-use dotenvy_macro::dotenv;
+use std::collections::HashMap;
+use super::error::LlmError;
+use super::mock::MockProvider;
+use super::ollama::OllamaProvider;
+use super::openai::OpenAiProvider;
+use super::provider::{ChatMessage, ChatRequest, LlmProvider};
+use super::settings::LlmSettings;
 
-// Generate a client
-fn gen_client() -> Client {
-    reqwest::Client::new()
+// providers that can be chosen per user (ai_provider preference) or per request
+pub const PROVIDERS: [&str; 3] = ["openai", "ollama", "mock"];
+
+// builds a provider by name, LLM_PROVIDER (default "openai") when none is given
+pub fn select_provider(name: Option<&str>, settings: &LlmSettings) -> Result<Box<dyn LlmProvider>, String> {
+    let name = name
+        .map(str::to_string)
+        .unwrap_or_else(|| std::env::var("LLM_PROVIDER").unwrap_or_else(|_| "openai".to_string()));
+
+    match name.as_str() {
+        "openai" => Ok(Box::new(OpenAiProvider::new(settings)?)),
+        "ollama" => Ok(Box::new(OllamaProvider::new(settings))),
+        "mock" => Ok(Box::new(MockProvider)),
+        other => Err(format!("Unknown AI provider: {} (expected one of: {})", other, PROVIDERS.join(", "))),
+    }
 }
 
-// Grabs OpenAI API key from .env file
-fn get_openai_key() -> String {
-    dotenv!("OPENAI_API_KEY").to_string()
+// provider and model for a request, falling back to the user's preferences
+// a model preference only applies when the provider isn't overridden, it may not exist elsewhere
+pub fn resolve_provider(provider: Option<String>, model: Option<String>, preferences: &HashMap<String, String>, settings: &LlmSettings) -> Result<(Box<dyn LlmProvider>, Option<String>), String> {
+    match provider {
+        Some(provider) => Ok((select_provider(Some(&provider), settings)?, model)),
+        None => Ok((
+            select_provider(preferences.get("ai_provider").map(String::as_str), settings)?,
+            model.or_else(|| preferences.get("ai_model").cloned()),
+        )),
+    }
 }
 
-// API call to GPT-4o-mini model
-pub async fn call_neuro(prompt: &str) -> Result<String, String> {
-    // HTTP POST to OpenAI
-    let response = gen_client()
-        .post("https://api.openai.com/v1/chat/completions")
-        .bearer_auth(get_openai_key())
-        .json(&serde_json::json!({
-            "model": "gpt-4o-mini",
-            "messages": [{
-                "role": "user",
-                "content": prompt
-            }]
-        }))
-        .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
-
-    // This bottom code to parse the response was produced by AI
-    let body = response.text().await.map_err(|e| e.to_string())?;
-    let json: serde_json::Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
-    let content = json["choices"][0]["message"]["content"]
-        .as_str()
-        .unwrap_or("⚠️ Could not extract message content")
-        .to_string();
-
-    Ok(content)
+// Single prompt to the chosen provider
+pub async fn call_neuro(prompt: &str, provider: &dyn LlmProvider, model: Option<String>) -> Result<String, LlmError> {
+    let request = ChatRequest {
+        model,
+        messages: vec![ChatMessage::user(prompt)],
+        schema: None,
+    };
+
+    provider.complete(&request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn call_neuro_sends_the_prompt_alone() {
+        let answer = call_neuro("What is a mitochondrion?", &MockProvider, None).await.unwrap();
+        assert_eq!(answer, "[mock] 1 message(s), last prompt: What is a mitochondrion?");
+    }
+
+    #[tokio::test]
+    async fn call_neuro_uses_the_chosen_model() {
+        let answer = call_neuro("hello", &MockProvider, Some("small".to_string())).await.unwrap();
+        assert!(answer.starts_with("[small] "));
+    }
 }
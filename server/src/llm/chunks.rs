@@ -0,0 +1,85 @@
+// longest chunk, longer sections are split between paragraphs
+const MAX_CHUNK_CHARS: usize = 1500;
+
+// Piece of a note under one heading
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Chunk {
+    pub heading: String, // heading path, e.g. "Setup > Install", empty before the first heading
+    pub text: String,
+}
+
+// level and title of an ATX heading line ("## Title")
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[level..];
+
+    if (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' ')) {
+        Some((level, rest.trim().trim_end_matches('#').trim()))
+    } else {
+        None
+    }
+}
+
+// splits an overlong section between paragraphs (or hard, for one giant paragraph)
+fn split_section(text: &str) -> Vec<String> {
+    let mut pieces: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for paragraph in text.split("\n\n").map(str::trim).filter(|paragraph| !paragraph.is_empty()) {
+        if !current.is_empty() && current.chars().count() + paragraph.chars().count() + 2 > MAX_CHUNK_CHARS {
+            pieces.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(paragraph);
+
+        while current.chars().count() > MAX_CHUNK_CHARS {
+            let split = current.char_indices().nth(MAX_CHUNK_CHARS).map(|(index, _)| index).unwrap_or(current.len());
+            let rest = current.split_off(split);
+            pieces.push(std::mem::replace(&mut current, rest));
+        }
+    }
+
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+    pieces
+}
+
+// CHUNK MARKDOWN (one chunk per heading section, headings inside code blocks are ignored)
+pub fn chunk_markdown(markdown: &str) -> Vec<Chunk> {
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut path: Vec<(usize, String)> = Vec::new();
+    let mut section = String::new();
+    let mut in_code = false;
+
+    let mut flush = |path: &[(usize, String)], section: &mut String| {
+        let heading = path.iter().map(|(_, title)| title.as_str()).collect::<Vec<_>>().join(" > ");
+        for text in split_section(section) {
+            chunks.push(Chunk { heading: heading.clone(), text });
+        }
+        section.clear();
+    };
+
+    for line in markdown.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+        }
+
+        match heading(line).filter(|_| !in_code) {
+            Some((level, title)) => {
+                flush(&path, &mut section);
+                path.retain(|(parent, _)| *parent < level);
+                path.push((level, title.to_string()));
+            }
+            None => {
+                section.push_str(line);
+                section.push('\n');
+            }
+        }
+    }
+    flush(&path, &mut section);
+
+    chunks
+}
@@ -0,0 +1,214 @@
+use super::error::LlmError;
+use super::provider::{ChatMessage, ChatRequest, LlmProvider};
+
+// system prompt new threads start with
+const DEFAULT_SYSTEM_PROMPT: &str = "You are Neuro, an assistant inside a note-taking app. Respond in markdown and keep answers concise.";
+// longest thread title, taken from the first prompt
+const TITLE_LENGTH: usize = 60;
+// rough per-message overhead (role and separators) on top of the content
+const MESSAGE_OVERHEAD: usize = 4;
+
+// Conversation thread attached to a note or notebook
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Thread {
+    pub id: String,
+    pub scope: String, // what the thread is attached to
+    pub title: String,
+    pub system: String,
+    pub messages: Vec<ChatMessage>, // user and assistant turns, oldest first
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+// Thread listing entry
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ThreadSummary {
+    pub id: String,
+    pub title: String,
+    pub turns: usize,
+    pub updated_at: i64,
+}
+
+// Implementation for thread methods
+impl Thread {
+    // empty thread titled after its first prompt
+    pub fn new(id: String, scope: String, prompt: &str, now: i64) -> Self {
+        let title: String = prompt.trim().chars().take(TITLE_LENGTH).collect();
+
+        Self {
+            id,
+            scope,
+            title,
+            system: DEFAULT_SYSTEM_PROMPT.to_string(),
+            messages: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn summary(&self) -> ThreadSummary {
+        ThreadSummary {
+            id: self.id.clone(),
+            title: self.title.clone(),
+            turns: self.messages.len(),
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+// tokens the history sent with each request may use (LLM_CONTEXT_TOKENS, default 6000)
+// kept below the model's window so there is room left for the answer
+pub fn context_budget() -> usize {
+    std::env::var("LLM_CONTEXT_TOKENS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|tokens| *tokens > 0)
+        .unwrap_or(6000)
+}
+
+// estimated tokens of a message (about 4 characters a token, which holds for english text)
+pub fn estimate_tokens(message: &ChatMessage) -> usize {
+    message.content.chars().count().div_ceil(4) + MESSAGE_OVERHEAD
+}
+
+// how many of the oldest turns have to go for the rest (and the system prompt) to fit the budget
+// a conversation never starts on an assistant turn, so its answer goes with the question
+fn turns_over_budget(system: &str, messages: &[ChatMessage], budget: usize) -> usize {
+    let mut used = estimate_tokens(&ChatMessage::system(system));
+    let mut keep = 0;
+
+    for message in messages.iter().rev() {
+        used += estimate_tokens(message);
+        if used > budget {
+            break;
+        }
+        keep += 1;
+    }
+
+    let mut drop = messages.len() - keep;
+    while messages.get(drop).is_some_and(|message| message.role == "assistant") {
+        drop += 1;
+    }
+
+    drop
+}
+
+// system prompt and the newest turns that fit the budget, what is actually sent to the model
+// the latest prompt is always sent, even when it alone is over budget
+pub fn context_window(thread: &Thread, budget: usize) -> Vec<ChatMessage> {
+    let drop = turns_over_budget(&thread.system, &thread.messages, budget).min(thread.messages.len().saturating_sub(1));
+
+    std::iter::once(ChatMessage::system(&thread.system))
+        .chain(thread.messages[drop..].iter().cloned())
+        .collect()
+}
+
+// removes the oldest turns beyond the budget from the thread itself, returns how many went
+pub fn truncate_turns(thread: &mut Thread, budget: usize) -> usize {
+    let drop = turns_over_budget(&thread.system, &thread.messages, budget);
+    thread.messages.drain(..drop);
+    drop
+}
+
+// CONTINUE CONVERSATION
+// adds the prompt and the model's answer to the thread, the thread is left as it was on failure
+pub async fn continue_conversation(thread: &mut Thread, prompt: String, provider: &dyn LlmProvider, model: Option<String>, now: i64) -> Result<String, LlmError> {
+    thread.messages.push(ChatMessage::user(prompt));
+
+    let request = ChatRequest {
+        model,
+        messages: context_window(thread, context_budget()),
+        schema: None,
+    };
+
+    match provider.complete(&request).await {
+        Ok(reply) => {
+            thread.messages.push(ChatMessage::assistant(reply.clone()));
+            thread.updated_at = now;
+            Ok(reply)
+        }
+        Err(e) => {
+            thread.messages.pop();
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock::MockProvider;
+
+    // 40 characters, 14 tokens with the overhead
+    fn turn(text: &str) -> String {
+        format!("{:<40}", text)
+    }
+
+    // one-character system prompt (5 tokens) and five turns, the last one a new prompt
+    fn thread() -> Thread {
+        let mut thread = Thread::new("thread".to_string(), "note".to_string(), "first", 0);
+        thread.system = "s".to_string();
+        thread.messages = vec![
+            ChatMessage::user(turn("u1")),
+            ChatMessage::assistant(turn("a1")),
+            ChatMessage::user(turn("u2")),
+            ChatMessage::assistant(turn("a2")),
+            ChatMessage::user(turn("u3")),
+        ];
+        thread
+    }
+
+    fn contents(messages: &[ChatMessage]) -> Vec<String> {
+        messages.iter().map(|message| message.content.trim().to_string()).collect()
+    }
+
+    #[test]
+    fn everything_fits_the_budget() {
+        let thread = thread();
+        let window = context_window(&thread, 1000);
+
+        assert_eq!(window[0].role, "system");
+        assert_eq!(contents(&window[1..]), ["u1", "a1", "u2", "a2", "u3"]);
+    }
+
+    #[test]
+    fn oldest_turns_are_dropped() {
+        let mut thread = thread();
+        let window = context_window(&thread, 5 + 14 * 3);
+        assert_eq!(contents(&window[1..]), ["u2", "a2", "u3"]);
+
+        assert_eq!(truncate_turns(&mut thread, 5 + 14 * 3), 2);
+        assert_eq!(contents(&thread.messages), ["u2", "a2", "u3"]);
+    }
+
+    #[test]
+    fn an_answer_goes_with_its_question() {
+        // a2 and u3 would fit, but a window never starts on an assistant turn
+        let mut thread = thread();
+        let window = context_window(&thread, 5 + 14 * 2);
+        assert_eq!(contents(&window[1..]), ["u3"]);
+
+        assert_eq!(truncate_turns(&mut thread, 5 + 14 * 2), 4);
+        assert_eq!(contents(&thread.messages), ["u3"]);
+    }
+
+    #[test]
+    fn the_latest_prompt_is_always_sent() {
+        let thread = thread();
+        let window = context_window(&thread, 1);
+
+        assert_eq!(window.len(), 2);
+        assert_eq!(contents(&window[1..]), ["u3"]);
+    }
+
+    #[tokio::test]
+    async fn conversation_keeps_both_turns() {
+        let mut thread = Thread::new("thread".to_string(), "note".to_string(), "hello", 0);
+        let reply = continue_conversation(&mut thread, "hello".to_string(), &MockProvider, None, 10).await.unwrap();
+
+        assert_eq!(reply, "[mock] 2 message(s), last prompt: hello");
+        assert_eq!(thread.messages.len(), 2);
+        assert_eq!(thread.messages[1].role, "assistant");
+        assert_eq!(thread.updated_at, 10);
+    }
+}
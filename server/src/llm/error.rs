@@ -0,0 +1,159 @@
+use reqwest::header::HeaderMap;
+use reqwest::{Response, StatusCode};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+// Errors returned by LLM providers
+#[derive(Debug, Clone, PartialEq)]
+pub enum LlmError {
+    Auth(String),              // missing, invalid or revoked API key
+    RateLimited(Option<u64>),  // too many requests, seconds until the provider takes more (if it said)
+    Quota(String),             // out of credit or over the plan's limit, waiting won't help
+    ContextTooLong(String),    // prompt (notes, thread) is more than the model takes
+    Timeout,                   // no response, or the stream stalled, within LLM_TIMEOUT
+    Network(String),           // the provider couldn't be reached
+    Server(u16, String),       // the provider failed (5xx) or refused the request for another reason
+    Malformed(String),         // a response that doesn't look like the provider's API
+}
+
+// Implementation for llm error methods
+impl LlmError {
+    // short name the frontend can match on
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LlmError::Auth(_) => "ai_auth",
+            LlmError::RateLimited(_) => "ai_rate_limited",
+            LlmError::Quota(_) => "ai_quota",
+            LlmError::ContextTooLong(_) => "ai_context_too_long",
+            LlmError::Timeout => "ai_timeout",
+            LlmError::Network(_) => "ai_network",
+            LlmError::Server(_, _) => "ai_server",
+            LlmError::Malformed(_) => "ai_malformed",
+        }
+    }
+
+    // seconds the frontend should wait before retrying, if any
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            LlmError::RateLimited(seconds) => *seconds,
+            _ => None,
+        }
+    }
+
+    // worth sending again: rate limits, timeouts, dropped connections and provider outages
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LlmError::RateLimited(_) | LlmError::Timeout | LlmError::Network(_) => true,
+            LlmError::Server(status, _) => *status >= 500 || *status == 408,
+            _ => false,
+        }
+    }
+
+    // error for a response with a failed status, from its body and headers
+    pub async fn from_response(response: Response) -> Self {
+        let status = response.status();
+        let retry_after = retry_after(response.headers());
+        let body = response.text().await.unwrap_or_default();
+
+        classify(status, retry_after, &body)
+    }
+
+    // error reported inside an otherwise successful response (part way through a stream), if any
+    pub fn reported(json: &serde_json::Value) -> Option<Self> {
+        match json["error"].is_null() {
+            true => None,
+            false => Some(classify(StatusCode::BAD_GATEWAY, None, &json.to_string())),
+        }
+    }
+}
+
+// Retry-After in seconds, or OpenAI's retry-after-ms rounded up
+fn retry_after(headers: &HeaderMap) -> Option<u64> {
+    let header = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<f64>().ok();
+
+    header("retry-after-ms")
+        .map(|ms| (ms / 1000.0).ceil() as u64)
+        .or_else(|| header("retry-after").map(|seconds| seconds.ceil() as u64))
+}
+
+// sorts a failed response into an error
+// OpenAI-compatible servers answer { "error": { "message", "code" } }, Ollama { "error": "..." }
+fn classify(status: StatusCode, retry_after: Option<u64>, body: &str) -> LlmError {
+    let json: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+    let message = json["error"]["message"]
+        .as_str()
+        .or_else(|| json["error"].as_str())
+        .unwrap_or(body)
+        .trim()
+        .to_string();
+    let code = json["error"]["code"].as_str().or_else(|| json["error"]["type"].as_str()).unwrap_or_default();
+    let lowercase = message.to_lowercase();
+
+    if code == "insufficient_quota" || lowercase.contains("quota") || status == StatusCode::PAYMENT_REQUIRED {
+        return LlmError::Quota(message);
+    }
+    if code == "context_length_exceeded" || lowercase.contains("context length") || lowercase.contains("maximum context") {
+        return LlmError::ContextTooLong(message);
+    }
+
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => LlmError::Auth(message),
+        StatusCode::TOO_MANY_REQUESTS => LlmError::RateLimited(retry_after),
+        StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => LlmError::Timeout,
+        _ => LlmError::Server(status.as_u16(), message),
+    }
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LlmError::Auth(message) => write!(f, "The AI provider refused the API key: {}", message),
+            LlmError::RateLimited(Some(seconds)) => write!(f, "The AI provider is busy, retry after {} seconds", seconds),
+            LlmError::RateLimited(None) => write!(f, "The AI provider is busy, retry in a moment"),
+            LlmError::Quota(message) => write!(f, "The AI provider's quota is used up: {}", message),
+            LlmError::ContextTooLong(message) => write!(f, "Too much text for the model, try a shorter note or thread: {}", message),
+            LlmError::Timeout => write!(f, "The AI provider took too long to answer"),
+            LlmError::Network(message) => write!(f, "Could not reach the AI provider: {}", message),
+            LlmError::Server(status, message) => write!(f, "The AI provider failed ({}): {}", status, message),
+            LlmError::Malformed(message) => write!(f, "Unexpected answer from the AI provider: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            LlmError::Timeout
+        } else if error.is_decode() {
+            LlmError::Malformed(error.to_string())
+        } else {
+            LlmError::Network(error.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for LlmError {
+    fn from(error: serde_json::Error) -> Self {
+        LlmError::Malformed(error.to_string())
+    }
+}
+
+// the local commands still report errors as text
+impl From<LlmError> for String {
+    fn from(error: LlmError) -> Self {
+        error.to_string()
+    }
+}
+
+// sent to the frontend as { kind, message, retry_after }, like database errors
+impl Serialize for LlmError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("LlmError", 3)?;
+        error.serialize_field("kind", self.kind())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.serialize_field("retry_after", &self.retry_after())?;
+        error.end()
+    }
+}
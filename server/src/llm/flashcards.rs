@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+use super::actions::{fill, structured};
+use super::error::LlmError;
+use super::provider::LlmProvider;
+
+// cards asked for when the caller doesn't say, and the most one request may ask for
+pub const DEFAULT_CARDS: usize = 10;
+pub const MAX_CARDS: usize = 30;
+// review grades, SM-2's 0 (blackout) to 5 (perfect recall), 3 and up counts as remembered
+pub const GRADES: std::ops::RangeInclusive<u8> = 0..=5;
+const PASSING_GRADE: u8 = 3;
+// SM-2 ease factor bounds
+const STARTING_EASE: f64 = 2.5;
+const MINIMUM_EASE: f64 = 1.3;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+const GENERATE: &str = "Write up to {count} flashcards for studying the note below. Each card asks one \
+question about a single fact, definition or idea from the note, with a short answer taken from the note. \
+Prefer the most important ideas, don't repeat a card, and don't ask about anything the note doesn't say.\n\nNote:\n{text}";
+
+// Question and answer card, generated from a note
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Flashcard {
+    pub id: String,
+    pub note_id: String, // note the card came from (its file name for local notebooks)
+    pub question: String,
+    pub answer: String,
+    pub created_at: i64,
+}
+
+// Where a card is in SM-2, per user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub ease: f64,
+    pub interval: u32,    // days between the last review and the next
+    pub repetitions: u32, // reviews in a row graded PASSING_GRADE or more
+    pub due: i64,         // study day the card is next due (see study_day), new cards are due straight away
+    pub last_grade: Option<u8>,
+    pub reviewed_at: Option<i64>,
+}
+
+// Card with where the user is with it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StudyCard {
+    pub card: Flashcard,
+    pub schedule: Schedule,
+}
+
+#[derive(Deserialize)]
+struct GeneratedCard {
+    question: String,
+    answer: String,
+}
+
+#[derive(Deserialize)]
+struct GeneratedCards {
+    cards: Vec<GeneratedCard>,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            ease: STARTING_EASE,
+            interval: 0,
+            repetitions: 0,
+            due: 0,
+            last_grade: None,
+            reviewed_at: None,
+        }
+    }
+}
+
+// Implementation for schedule methods
+impl Schedule {
+    // REVIEW (SM-2): a pass waits 1, then 6, then interval x ease days, a fail starts the card over
+    // the ease factor moves with every grade but never drops below MINIMUM_EASE
+    pub fn review(&self, grade: u8, today: i64, now: i64) -> Self {
+        let grade = grade.min(*GRADES.end());
+        let (repetitions, interval) = match grade >= PASSING_GRADE {
+            true => {
+                let interval = match self.repetitions {
+                    0 => 1,
+                    1 => 6,
+                    _ => (self.interval as f64 * self.ease).round() as u32,
+                };
+                (self.repetitions + 1, interval)
+            }
+            false => (0, 1),
+        };
+
+        let miss = (5 - grade) as f64;
+        let ease = (self.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(MINIMUM_EASE);
+
+        Self {
+            ease,
+            interval,
+            repetitions,
+            due: today + interval as i64,
+            last_grade: Some(grade),
+            reviewed_at: Some(now),
+        }
+    }
+}
+
+// day number reviews are scheduled in, in the user's timezone so "today" ends at their midnight
+pub fn study_day(now: i64, utc_offset_minutes: i32) -> i64 {
+    (now + utc_offset_minutes as i64 * 60).div_euclid(SECONDS_PER_DAY)
+}
+
+// DUE CARDS (most overdue first, new cards after reviews), at most `limit` of them
+pub fn due_cards(cards: Vec<Flashcard>, schedules: &HashMap<String, Schedule>, today: i64, limit: Option<usize>) -> Vec<StudyCard> {
+    let mut due: Vec<StudyCard> = cards
+        .into_iter()
+        .map(|card| {
+            let schedule = schedules.get(&card.id).cloned().unwrap_or_default();
+            StudyCard { card, schedule }
+        })
+        .filter(|study| study.schedule.due <= today)
+        .collect();
+
+    due.sort_by_key(|study| (study.schedule.reviewed_at.is_none(), study.schedule.due, study.card.created_at));
+    due.truncate(limit.unwrap_or(usize::MAX));
+    due
+}
+
+fn cards_schema(count: usize) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "cards": {
+                "type": "array",
+                "minItems": 1,
+                "maxItems": count,
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "question": { "type": "string", "minLength": 1 },
+                        "answer": { "type": "string", "minLength": 1 },
+                    },
+                    "required": ["question", "answer"],
+                    "additionalProperties": false,
+                },
+            },
+        },
+        "required": ["cards"],
+        "additionalProperties": false,
+    })
+}
+
+// questions compared without case or surrounding whitespace
+fn question_key(question: &str) -> String {
+    question.trim().to_lowercase()
+}
+
+// GENERATE FLASHCARDS (from a note, questions already in the deck are skipped)
+pub async fn generate_flashcards(note_id: &str, note: &str, count: usize, existing: &[Flashcard], provider: &dyn LlmProvider, model: Option<String>, now: i64) -> Result<Vec<Flashcard>, LlmError> {
+    let count = count.clamp(1, MAX_CARDS);
+    let prompt = fill(GENERATE, &[("count", &count.to_string()), ("text", note)]);
+    let generated: GeneratedCards = structured(provider, model, prompt, cards_schema(count)).await?;
+
+    let mut seen: HashSet<String> = existing.iter().map(|card| question_key(&card.question)).collect();
+
+    Ok(generated
+        .cards
+        .into_iter()
+        .filter(|card| seen.insert(question_key(&card.question)))
+        .map(|card| Flashcard {
+            id: Uuid::new_v4().to_string(),
+            note_id: note_id.to_string(),
+            question: card.question.trim().to_string(),
+            answer: card.answer.trim().to_string(),
+            created_at: now,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock::MockProvider;
+
+    fn card(id: &str, created_at: i64) -> Flashcard {
+        Flashcard {
+            id: id.to_string(),
+            note_id: "note".to_string(),
+            question: format!("Question {}", id),
+            answer: format!("Answer {}", id),
+            created_at,
+        }
+    }
+
+    fn reviewed(due: i64) -> Schedule {
+        Schedule { due, reviewed_at: Some(0), ..Default::default() }
+    }
+
+    #[test]
+    fn review_follows_sm2() {
+        let mut schedule = Schedule::default();
+        let mut intervals = Vec::new();
+
+        for grade in [5, 4, 4, 2, 3] {
+            schedule = schedule.review(grade, 100, 0);
+            intervals.push(schedule.interval);
+        }
+
+        assert_eq!(intervals, [1, 6, 16, 1, 1]);
+        assert_eq!(schedule.repetitions, 1);
+        assert_eq!(schedule.due, 101);
+        assert_eq!(schedule.last_grade, Some(3));
+    }
+
+    #[test]
+    fn ease_never_drops_below_the_minimum() {
+        let mut schedule = Schedule::default();
+
+        for _ in 0..10 {
+            schedule = schedule.review(0, 0, 0);
+            assert!(schedule.ease >= MINIMUM_EASE);
+        }
+        assert_eq!(schedule.ease, MINIMUM_EASE);
+    }
+
+    #[test]
+    fn grades_above_five_count_as_five() {
+        let schedule = Schedule::default().review(9, 0, 0);
+        assert_eq!(schedule.last_grade, Some(5));
+        assert!((schedule.ease - 2.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn due_cards_put_overdue_reviews_before_new_cards() {
+        let cards = vec![card("new", 1), card("later", 2), card("today", 3), card("overdue", 4)];
+        let schedules = HashMap::from([
+            ("later".to_string(), reviewed(11)),
+            ("today".to_string(), reviewed(10)),
+            ("overdue".to_string(), reviewed(7)),
+        ]);
+
+        let due: Vec<String> = due_cards(cards.clone(), &schedules, 10, None)
+            .into_iter()
+            .map(|study| study.card.id)
+            .collect();
+        assert_eq!(due, ["overdue", "today", "new"]);
+
+        assert_eq!(due_cards(cards, &schedules, 10, Some(1)).len(), 1);
+    }
+
+    #[test]
+    fn study_days_follow_the_users_midnight() {
+        // 23:30 UTC is already the next day an hour east of it
+        let now = SECONDS_PER_DAY - 30 * 60;
+        assert_eq!(study_day(now, 0), 0);
+        assert_eq!(study_day(now, 60), 1);
+        assert_eq!(study_day(now, -60), 0);
+    }
+
+    #[tokio::test]
+    async fn generated_cards_skip_existing_questions() {
+        let cards = generate_flashcards("note", "Cells have a nucleus.", 3, &[], &MockProvider, None, 5).await.unwrap();
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].note_id, "note");
+        assert_eq!(cards[0].created_at, 5);
+
+        // the mock asks the same question again
+        let again = generate_flashcards("note", "Cells have a nucleus.", 3, &cards, &MockProvider, None, 6).await.unwrap();
+        assert!(again.is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use reqwest::{Client, RequestBuilder, Response};
+use std::future::Future;
+use std::time::Duration;
+use super::error::LlmError;
+use crate::db::client::env_seconds;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// first retry waits up to this long, doubling each time up to the cap
+const BASE_DELAY_MS: u64 = 500;
+const MAX_DELAY_MS: u64 = 8_000;
+// a provider asking for a longer wait than this (seconds) is reported instead of waited on
+const MAX_RETRY_AFTER: u64 = 30;
+
+// HTTP client for providers, a server that never accepts the connection fails fast
+pub fn client() -> Client {
+    Client::builder().connect_timeout(CONNECT_TIMEOUT).build().unwrap_or_default()
+}
+
+// LLM_TIMEOUT (default 60 seconds), the longest wait for a response, or between two pieces of a streamed one
+fn timeout() -> Duration {
+    Duration::from_secs(env_seconds("LLM_TIMEOUT", 60) as u64)
+}
+
+// LLM_MAX_RETRIES (default 2), extra attempts after a retryable failure, 0 turns retries off
+fn max_retries() -> u32 {
+    std::env::var("LLM_MAX_RETRIES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(2)
+}
+
+// exponential backoff with full jitter, so requests that failed together don't retry together
+fn backoff(retry: u32) -> Duration {
+    let cap = BASE_DELAY_MS.saturating_mul(1 << retry.min(16)).min(MAX_DELAY_MS);
+    Duration::from_millis(OsRng.next_u64() % (cap + 1))
+}
+
+// waits on a network step for at most LLM_TIMEOUT
+pub async fn within_timeout<T>(step: impl Future<Output = Result<T, reqwest::Error>>) -> Result<T, LlmError> {
+    match tokio::time::timeout(timeout(), step).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(LlmError::Timeout),
+    }
+}
+
+// runs an attempt again while it fails in a retryable way
+// the provider's Retry-After is respected, unless it asks for longer than is worth waiting
+pub async fn with_retries<T, F, Fut>(mut attempt: F) -> Result<T, LlmError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, LlmError>>,
+{
+    let retries = max_retries();
+    let mut retry = 0;
+
+    loop {
+        match attempt().await {
+            Err(e) if e.is_retryable() && retry < retries => {
+                let delay = match e.retry_after() {
+                    Some(seconds) if seconds > MAX_RETRY_AFTER => return Err(e),
+                    Some(seconds) => Duration::from_secs(seconds).max(backoff(retry)),
+                    None => backoff(retry),
+                };
+                retry += 1;
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+// SEND (the request is built again for every attempt), a failed status becomes a typed error
+// only getting the response is retried, a stream that already started answering is not
+pub async fn send(request: impl Fn() -> RequestBuilder) -> Result<Response, LlmError> {
+    with_retries(|| async {
+        let response = within_timeout(request().send()).await?;
+        match response.status().is_success() {
+            true => Ok(response),
+            false => Err(LlmError::from_response(response).await),
+        }
+    })
+    .await
+}
@@ -0,0 +1,26 @@
+// Splits a response body into lines as chunks arrive, keeping a partial last line until the rest comes
+// (bytes are only decoded once a line is complete, so characters split across chunks survive)
+#[derive(Default)]
+pub struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+// Implementation for line buffer methods
+impl LineBuffer {
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(bytes);
+
+        let mut lines: Vec<String> = Vec::new();
+        while let Some(end) = self.pending.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            lines.push(String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).to_string());
+        }
+
+        lines
+    }
+}
+
+// payload of a server-sent-events data line, other fields and comments are skipped
+pub fn sse_data(line: &str) -> Option<&str> {
+    line.strip_prefix("data:").map(str::trim_start)
+}
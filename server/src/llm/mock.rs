@@ -0,0 +1,80 @@
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use super::error::LlmError;
+use super::schema::example;
+use super::provider::{ChatRequest, LlmProvider, TokenSink, Usage};
+
+// size of mock embeddings
+const DIMENSIONS: usize = 64;
+
+// Answers without a network call, the same request always gets the same answer (tests and offline use)
+pub struct MockProvider;
+
+#[async_trait]
+impl LlmProvider for MockProvider {
+    fn default_model(&self) -> &str {
+        "mock"
+    }
+
+    async fn complete(&self, request: &ChatRequest) -> Result<String, LlmError> {
+        let prompt = request.messages
+            .iter()
+            .rev()
+            .find(|message| message.role == "user")
+            .map(|message| message.content.as_str())
+            .unwrap_or_default();
+
+        // structured requests get the smallest answer that fits, with the prompt in its text
+        if let Some(schema) = &request.schema {
+            return Ok(example(schema, prompt).to_string());
+        }
+
+        Ok(format!(
+            "[{}] {} message(s), last prompt: {}",
+            request.model.as_deref().unwrap_or(self.default_model()),
+            request.messages.len(),
+            prompt
+        ))
+    }
+
+    fn embedding_model(&self) -> String {
+        "mock:hashed-words".to_string()
+    }
+
+    // each word is hashed into a bucket, so texts sharing words point the same way
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, LlmError> {
+        Ok(texts
+            .iter()
+            .map(|text| {
+                let mut vector = vec![0.0f32; DIMENSIONS];
+                for word in text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+                    let hash = Sha256::digest(word.to_lowercase().as_bytes());
+                    vector[hash[0] as usize % DIMENSIONS] += 1.0;
+                }
+                vector
+            })
+            .collect())
+    }
+
+    // streams the same answer a word at a time, usage counts words
+    async fn stream(&self, request: &ChatRequest, on_token: TokenSink<'_>) -> Result<Usage, LlmError> {
+        let content = self.complete(request).await?;
+        let mut completion_tokens: u64 = 0;
+
+        for word in content.split_inclusive(' ') {
+            on_token(word);
+            completion_tokens += 1;
+        }
+
+        let prompt_tokens = request.messages
+            .iter()
+            .map(|message| message.content.split_whitespace().count() as u64)
+            .sum::<u64>();
+
+        Ok(Usage {
+            prompt_tokens: Some(prompt_tokens),
+            completion_tokens: Some(completion_tokens),
+            total_tokens: Some(prompt_tokens + completion_tokens),
+        })
+    }
+}
@@ -1 +1,17 @@
-pub mod ai;
\ No newline at end of file
+pub mod ai;
+pub mod provider;
+pub mod openai;
+pub mod ollama;
+pub mod mock;
+pub mod lines;
+pub mod stream;
+pub mod conversation;
+pub mod chunks;
+pub mod rag;
+pub mod vectors;
+pub mod settings;
+pub mod error;
+pub mod http;
+pub mod schema;
+pub mod actions;
+pub mod flashcards;
@@ -0,0 +1,135 @@
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use super::error::LlmError;
+use super::http::{client, send, within_timeout};
+use super::lines::LineBuffer;
+use super::provider::{ChatRequest, LlmProvider, TokenSink, Usage};
+use super::settings::LlmSettings;
+
+const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+const DEFAULT_MODEL: &str = "llama3.2";
+const DEFAULT_EMBEDDING_MODEL: &str = "nomic-embed-text";
+
+// Models served by a local Ollama install
+pub struct OllamaProvider {
+    client: Client,
+    base_url: String,
+    model: String,
+    embedding_model: String,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+}
+
+// Implementation for ollama provider methods
+impl OllamaProvider {
+    // OLLAMA_BASE_URL, OLLAMA_MODEL and OLLAMA_EMBEDDING_MODEL override the defaults
+    // only the sampling settings apply, the key and server are for OpenAI-compatible ones
+    pub fn new(settings: &LlmSettings) -> Self {
+        Self {
+            client: client(),
+            base_url: std::env::var("OLLAMA_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string()),
+            model: std::env::var("OLLAMA_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_string()),
+            embedding_model: std::env::var("OLLAMA_EMBEDDING_MODEL").unwrap_or_else(|_| DEFAULT_EMBEDDING_MODEL.to_string()),
+            temperature: settings.temperature(),
+            max_tokens: settings.max_tokens(),
+        }
+    }
+
+    // HTTP POST to an endpoint of the Ollama API
+    fn post(&self, endpoint: &str) -> RequestBuilder {
+        self.client.post(format!("{}/api/{}", self.base_url.trim_end_matches('/'), endpoint))
+    }
+
+    // chat body, Ollama takes sampling settings as options (num_predict caps the answer)
+    fn chat_body(&self, request: &ChatRequest, stream: bool) -> serde_json::Value {
+        let mut options = serde_json::Map::new();
+        if let Some(temperature) = self.temperature {
+            options.insert("temperature".to_string(), temperature.into());
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            options.insert("num_predict".to_string(), max_tokens.into());
+        }
+
+        let mut body = serde_json::json!({
+            "model": request.model.as_deref().unwrap_or(&self.model),
+            "messages": request.messages,
+            "stream": stream,
+            "options": options,
+        });
+        // Ollama constrains the answer to a schema given as its format
+        if let Some(schema) = &request.schema {
+            body["format"] = schema.clone();
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn default_model(&self) -> &str {
+        &self.model
+    }
+
+    async fn complete(&self, request: &ChatRequest) -> Result<String, LlmError> {
+        // HTTP POST to the chat endpoint, one response instead of a stream
+        let body = self.chat_body(request, false);
+        let response = send(|| self.post("chat").json(&body)).await?;
+
+        let json: serde_json::Value = within_timeout(response.json()).await?;
+        json["message"]["content"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| LlmError::Malformed("no message content".to_string()))
+    }
+
+    fn embedding_model(&self) -> String {
+        format!("ollama:{}", self.embedding_model)
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, LlmError> {
+        // HTTP POST to the embed endpoint, one vector per input in the same order
+        let body = serde_json::json!({
+            "model": self.embedding_model,
+            "input": texts,
+        });
+        let response = send(|| self.post("embed").json(&body)).await?;
+
+        let json: serde_json::Value = within_timeout(response.json()).await?;
+        Ok(serde_json::from_value(json["embeddings"].clone())?)
+    }
+
+    async fn stream(&self, request: &ChatRequest, on_token: TokenSink<'_>) -> Result<Usage, LlmError> {
+        // HTTP POST, answered as one JSON object per line until "done"
+        let body = self.chat_body(request, true);
+        let mut response = send(|| self.post("chat").json(&body)).await?;
+
+        let mut lines = LineBuffer::default();
+
+        while let Some(chunk) = within_timeout(response.chunk()).await? {
+            for line in lines.push(&chunk).iter().filter(|line| !line.trim().is_empty()) {
+                let json: serde_json::Value = serde_json::from_str(line)?;
+                if let Some(error) = LlmError::reported(&json) {
+                    return Err(error);
+                }
+
+                if let Some(token) = json["message"]["content"].as_str().filter(|token| !token.is_empty()) {
+                    on_token(token);
+                }
+
+                // the final line carries the token counts
+                if json["done"].as_bool() == Some(true) {
+                    let prompt_tokens = json["prompt_eval_count"].as_u64();
+                    let completion_tokens = json["eval_count"].as_u64();
+
+                    return Ok(Usage {
+                        prompt_tokens,
+                        completion_tokens,
+                        total_tokens: prompt_tokens.zip(completion_tokens).map(|(prompt, completion)| prompt + completion),
+                    });
+                }
+            }
+        }
+
+        Ok(Usage::default())
+    }
+}
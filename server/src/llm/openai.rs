@@ -0,0 +1,164 @@
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use super::error::LlmError;
+use super::http::{client, send, within_timeout};
+use super::lines::{sse_data, LineBuffer};
+use super::provider::{ChatRequest, LlmProvider, TokenSink, Usage};
+use super::settings::LlmSettings;
+
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
+// Any server speaking the OpenAI chat completions API (OpenAI itself, LM Studio, vLLM, ...)
+pub struct OpenAiProvider {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    embedding_model: String,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+}
+
+// Implementation for openai provider methods
+impl OpenAiProvider {
+    // the user's own key and server first, then OPENAI_API_KEY, OPENAI_BASE_URL, OPENAI_MODEL
+    // and OPENAI_EMBEDDING_MODEL, all read at runtime
+    // the server's key is never sent to a server the user chose
+    pub fn new(settings: &LlmSettings) -> Result<Self, String> {
+        let (base_url, api_key) = match &settings.base_url {
+            Some(base_url) => (base_url.clone(), settings.api_key.clone()),
+            None => (
+                std::env::var("OPENAI_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string()),
+                settings.api_key.clone().or_else(|| std::env::var("OPENAI_API_KEY").ok().filter(|key| !key.is_empty())),
+            ),
+        };
+
+        // local OpenAI-compatible servers usually take no key, OpenAI itself always does
+        if api_key.is_none() && base_url == DEFAULT_BASE_URL {
+            return Err("No OpenAI API key, add your own in the AI settings or set OPENAI_API_KEY".to_string());
+        }
+
+        Ok(Self {
+            client: client(),
+            base_url,
+            api_key,
+            model: std::env::var("OPENAI_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_string()),
+            embedding_model: std::env::var("OPENAI_EMBEDDING_MODEL").unwrap_or_else(|_| DEFAULT_EMBEDDING_MODEL.to_string()),
+            temperature: settings.temperature(),
+            max_tokens: settings.max_tokens(),
+        })
+    }
+
+    // HTTP POST to an endpoint, with the key when there is one
+    fn post(&self, endpoint: &str) -> RequestBuilder {
+        let request = self.client.post(format!("{}/{}", self.base_url.trim_end_matches('/'), endpoint));
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+
+    // chat completions body, sampling settings only when they're set
+    fn chat_body(&self, request: &ChatRequest) -> serde_json::Value {
+        let mut body = serde_json::json!({
+            "model": request.model.as_deref().unwrap_or(&self.model),
+            "messages": request.messages,
+        });
+        if let Some(temperature) = self.temperature {
+            body["temperature"] = temperature.into();
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            body["max_tokens"] = max_tokens.into();
+        }
+        if let Some(schema) = &request.schema {
+            body["response_format"] = serde_json::json!({
+                "type": "json_schema",
+                "json_schema": { "name": "result", "schema": schema },
+            });
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn default_model(&self) -> &str {
+        &self.model
+    }
+
+    async fn complete(&self, request: &ChatRequest) -> Result<String, LlmError> {
+        // HTTP POST to the chat completions endpoint
+        let body = self.chat_body(request);
+        let response = send(|| self.post("chat/completions").json(&body)).await?;
+
+        let json: serde_json::Value = within_timeout(response.json()).await?;
+        json["choices"][0]["message"]["content"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| LlmError::Malformed("no message content".to_string()))
+    }
+
+    fn embedding_model(&self) -> String {
+        format!("openai:{}", self.embedding_model)
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, LlmError> {
+        // HTTP POST to the embeddings endpoint, one vector per input in the same order
+        let body = serde_json::json!({
+            "model": self.embedding_model,
+            "input": texts,
+        });
+        let response = send(|| self.post("embeddings").json(&body)).await?;
+
+        let json: serde_json::Value = within_timeout(response.json()).await?;
+        let data = json["data"].as_array().ok_or_else(|| LlmError::Malformed("no embeddings".to_string()))?;
+
+        data.iter()
+            .map(|item| Ok(serde_json::from_value(item["embedding"].clone())?))
+            .collect()
+    }
+
+    async fn stream(&self, request: &ChatRequest, on_token: TokenSink<'_>) -> Result<Usage, LlmError> {
+        // HTTP POST, answered as server-sent events ending in "data: [DONE]"
+        let mut body = self.chat_body(request);
+        body["stream"] = true.into();
+        body["stream_options"] = serde_json::json!({ "include_usage": true });
+
+        let mut response = send(|| self.post("chat/completions").json(&body)).await?;
+
+        let mut lines = LineBuffer::default();
+        let mut usage = Usage::default();
+
+        while let Some(chunk) = within_timeout(response.chunk()).await? {
+            for line in lines.push(&chunk) {
+                let Some(data) = sse_data(&line) else {
+                    continue;
+                };
+                if data == "[DONE]" {
+                    return Ok(usage);
+                }
+
+                let json: serde_json::Value = serde_json::from_str(data)?;
+                if let Some(error) = LlmError::reported(&json) {
+                    return Err(error);
+                }
+                if let Some(token) = json["choices"][0]["delta"]["content"].as_str().filter(|token| !token.is_empty()) {
+                    on_token(token);
+                }
+
+                // the last event before [DONE] carries the usage
+                if json["usage"].is_object() {
+                    usage = Usage {
+                        prompt_tokens: json["usage"]["prompt_tokens"].as_u64(),
+                        completion_tokens: json["usage"]["completion_tokens"].as_u64(),
+                        total_tokens: json["usage"]["total_tokens"].as_u64(),
+                    };
+                }
+            }
+        }
+
+        Ok(usage)
+    }
+}
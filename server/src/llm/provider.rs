@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+use super::error::LlmError;
+
+// Chat message sent to a model
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChatMessage {
+    pub role: String, // "system", "user" or "assistant"
+    pub content: String,
+}
+
+// Implementation for chat message methods
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self { role: "system".to_string(), content: content.into() }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: "user".to_string(), content: content.into() }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self { role: "assistant".to_string(), content: content.into() }
+    }
+}
+
+// Chat request, the model falls back to the provider's default when not given
+// with a schema the answer has to be a JSON object following it (providers that support it enforce it)
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub model: Option<String>,
+    pub messages: Vec<ChatMessage>,
+    pub schema: Option<serde_json::Value>,
+}
+
+// Token counts reported by the provider, None when it doesn't report them
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub total_tokens: Option<u64>,
+}
+
+// receives each piece of a streamed answer as it arrives
+pub type TokenSink<'a> = &'a mut (dyn FnMut(&str) + Send);
+
+// A backend that can answer chat requests
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    // model used when the request doesn't name one
+    fn default_model(&self) -> &str;
+
+    async fn complete(&self, request: &ChatRequest) -> Result<String, LlmError>;
+
+    // embedding model, vectors from different models can't be compared
+    fn embedding_model(&self) -> String;
+
+    // one vector per text, for semantic search
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, LlmError>;
+
+    // answers piece by piece, providers that can't stream send the whole answer at once
+    async fn stream(&self, request: &ChatRequest, on_token: TokenSink<'_>) -> Result<Usage, LlmError> {
+        let content = self.complete(request).await?;
+        on_token(&content);
+        Ok(Usage::default())
+    }
+}
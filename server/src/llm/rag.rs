@@ -0,0 +1,247 @@
+use std::collections::{BTreeSet, HashMap};
+use super::chunks::chunk_markdown;
+use super::conversation::context_budget;
+use super::error::LlmError;
+use super::provider::{ChatMessage, ChatRequest, LlmProvider};
+
+// excerpts retrieved per question (RAG_TOP_K, default 5)
+fn top_k() -> usize {
+    std::env::var("RAG_TOP_K")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|k| *k > 0)
+        .unwrap_or(5)
+}
+
+// length of the excerpt shown with a citation
+const EXCERPT_CHARS: usize = 200;
+// BM25 tuning, the usual defaults
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+// words too common to say anything about relevance
+const STOP_WORDS: [&str; 32] = [
+    "a", "an", "and", "are", "as", "at", "be", "by", "do", "does", "for", "from", "how", "in", "is", "it",
+    "of", "on", "or", "that", "the", "this", "to", "was", "what", "when", "where", "which", "who", "why", "with", "my",
+];
+
+const SYSTEM_PROMPT: &str = "You answer questions about the user's notes. Use only the numbered excerpts below. \
+Cite the excerpts you use inline like [1] or [2]. If the excerpts don't answer the question, say so instead of guessing. \
+Respond in markdown.";
+
+// Note chunk that can be retrieved and cited
+#[derive(Debug, Clone)]
+pub struct Passage {
+    pub note_id: String,
+    pub note: String,
+    pub heading: String,
+    pub text: String,
+}
+
+// Where part of an answer came from
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Citation {
+    pub index: usize, // the [n] used in the answer
+    pub note_id: String,
+    pub note: String,
+    pub heading: String,
+    pub excerpt: String,
+}
+
+// Answer grounded in the user's notes
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct GroundedAnswer {
+    pub answer: String,
+    pub citations: Vec<Citation>,
+}
+
+// lowercased words worth matching on
+fn terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .map(str::to_lowercase)
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+// PASSAGES (every note split into heading chunks), notes given as (id, name, markdown)
+pub fn passages(notes: Vec<(String, String, String)>) -> Vec<Passage> {
+    notes
+        .into_iter()
+        .flat_map(|(note_id, note, markdown)| {
+            chunk_markdown(&markdown).into_iter().map(move |chunk| Passage {
+                note_id: note_id.clone(),
+                note: note.clone(),
+                heading: chunk.heading,
+                text: chunk.text,
+            })
+        })
+        .collect()
+}
+
+// RANK PASSAGES (BM25 over the heading and text, best first, unmatched passages dropped)
+pub fn rank_passages(question: &str, passages: Vec<Passage>, limit: usize) -> Vec<Passage> {
+    let query: BTreeSet<String> = terms(question).into_iter().collect();
+    if query.is_empty() || passages.is_empty() {
+        return Vec::new();
+    }
+
+    let documents: Vec<Vec<String>> = passages
+        .iter()
+        .map(|passage| terms(&format!("{} {} {}", passage.note, passage.heading, passage.text)))
+        .collect();
+
+    let count = documents.len() as f64;
+    let average = documents.iter().map(Vec::len).sum::<usize>() as f64 / count;
+
+    let mut frequency: HashMap<&str, f64> = HashMap::new();
+    for document in &documents {
+        let unique: BTreeSet<&str> = document.iter().map(String::as_str).collect();
+        for term in unique.into_iter().filter(|term| query.contains(*term)) {
+            *frequency.entry(term).or_default() += 1.0;
+        }
+    }
+
+    let mut scored: Vec<(f64, Passage)> = documents
+        .iter()
+        .zip(passages)
+        .map(|(document, passage)| {
+            let length = document.len() as f64;
+            let score = query
+                .iter()
+                .map(|term| {
+                    let tf = document.iter().filter(|word| *word == term).count() as f64;
+                    let df = frequency.get(term.as_str()).copied().unwrap_or(0.0);
+                    let idf = (1.0 + (count - df + 0.5) / (df + 0.5)).ln();
+
+                    idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * length / average.max(1.0)))
+                })
+                .sum::<f64>();
+            (score, passage)
+        })
+        .filter(|(score, _)| *score > 0.0)
+        .collect();
+
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().take(limit).map(|(_, passage)| passage).collect()
+}
+
+// numbers cited as [1] or [1, 3] in an answer
+fn cited(answer: &str) -> BTreeSet<usize> {
+    answer
+        .split('[')
+        .skip(1)
+        .filter_map(|rest| rest.split_once(']').map(|(inside, _)| inside))
+        .flat_map(|inside| inside.split(',').filter_map(|number| number.trim().parse().ok()))
+        .collect()
+}
+
+// grounded prompt, excerpts are numbered and kept within the context budget
+// returns how many excerpts fit, later ones are left out
+fn grounded_messages(question: &str, passages: &[Passage]) -> (Vec<ChatMessage>, usize) {
+    let mut budget = context_budget().saturating_mul(4).saturating_sub(question.len() + SYSTEM_PROMPT.len());
+    let mut excerpts = String::new();
+    let mut included = 0;
+
+    for (index, passage) in passages.iter().enumerate() {
+        let excerpt = format!("[{}] {} > {}\n{}\n\n", index + 1, passage.note, passage.heading, passage.text);
+        if excerpt.len() > budget {
+            break;
+        }
+        budget -= excerpt.len();
+        excerpts.push_str(&excerpt);
+        included += 1;
+    }
+
+    if excerpts.is_empty() {
+        excerpts.push_str("(no notes matched the question)\n\n");
+    }
+
+    let messages = vec![
+        ChatMessage::system(SYSTEM_PROMPT),
+        ChatMessage::user(format!("Excerpts:\n\n{}Question: {}", excerpts, question)),
+    ];
+
+    (messages, included)
+}
+
+// ASK ABOUT NOTES
+// retrieves the passages most relevant to the question and answers from them, with citations
+pub async fn ask_about_notes(question: &str, notes: Vec<(String, String, String)>, provider: &dyn LlmProvider, model: Option<String>) -> Result<GroundedAnswer, LlmError> {
+    let mut retrieved = rank_passages(question, passages(notes), top_k());
+    let (messages, included) = grounded_messages(question, &retrieved);
+    retrieved.truncate(included);
+
+    let request = ChatRequest { model, messages, schema: None };
+
+    let answer = provider.complete(&request).await?;
+
+    // citations the answer actually uses, or every excerpt it was given if it cites none
+    let used = cited(&answer);
+    let citations = retrieved
+        .into_iter()
+        .enumerate()
+        .map(|(index, passage)| (index + 1, passage))
+        .filter(|(index, _)| used.is_empty() || used.contains(index))
+        .map(|(index, passage)| Citation {
+            index,
+            note_id: passage.note_id,
+            note: passage.note,
+            heading: passage.heading,
+            excerpt: passage.text.chars().take(EXCERPT_CHARS).collect(),
+        })
+        .collect();
+
+    Ok(GroundedAnswer { answer, citations })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passage(note_id: &str, heading: &str, text: &str) -> Passage {
+        Passage {
+            note_id: note_id.to_string(),
+            note: format!("Note {}", note_id),
+            heading: heading.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    fn library() -> Vec<Passage> {
+        vec![
+            passage("1", "Cooking", "Boil the pasta for ten minutes and drain it."),
+            passage("2", "Photosynthesis", "Photosynthesis turns light, water and carbon dioxide into glucose."),
+            passage("3", "Plants", "Leaves are green because of chlorophyll, which photosynthesis depends on."),
+        ]
+    }
+
+    fn ids(passages: &[Passage]) -> Vec<&str> {
+        passages.iter().map(|passage| passage.note_id.as_str()).collect()
+    }
+
+    #[test]
+    fn best_match_comes_first_and_unmatched_are_dropped() {
+        let ranked = rank_passages("How does photosynthesis make glucose?", library(), 5);
+        assert_eq!(ids(&ranked), ["2", "3"]);
+    }
+
+    #[test]
+    fn ranking_stops_at_the_limit() {
+        let ranked = rank_passages("photosynthesis", library(), 1);
+        assert_eq!(ids(&ranked), ["2"]);
+    }
+
+    #[test]
+    fn stop_words_alone_match_nothing() {
+        assert!(rank_passages("what is the", library(), 5).is_empty());
+        assert!(rank_passages("photosynthesis", Vec::new(), 5).is_empty());
+    }
+
+    #[test]
+    fn cited_numbers_are_collected() {
+        let used = cited("Plants make glucose [2], using chlorophyll [1, 3]. See [x] and [].");
+        assert_eq!(used.into_iter().collect::<Vec<_>>(), [1, 2, 3]);
+
+        assert!(cited("No citations here").is_empty());
+    }
+}
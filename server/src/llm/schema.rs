@@ -0,0 +1,199 @@
+use serde_json::{Map, Value};
+
+// The part of JSON Schema structured answers use: type (one or a list), properties, required,
+// additionalProperties false, items, enum, minItems, maxItems and minLength
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_i64() || number.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// whether a value is one of the schema's types, an integer also counts as a number
+fn has_type(value: &Value, schema: &Value) -> bool {
+    let actual = type_name(value);
+    let allowed = |expected: &str| expected == actual || (expected == "number" && actual == "integer");
+
+    match &schema["type"] {
+        Value::String(expected) => allowed(expected),
+        Value::Array(expected) => expected.iter().filter_map(Value::as_str).any(allowed),
+        _ => true,
+    }
+}
+
+// VALIDATE (the error names where the value went wrong, e.g. "$.items[2].priority")
+pub fn validate(value: &Value, schema: &Value) -> Result<(), String> {
+    check(value, schema, "$")
+}
+
+fn check(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    if !has_type(value, schema) {
+        return Err(format!("{} should be {}, not {}", path, schema["type"], type_name(value)));
+    }
+
+    if let Some(options) = schema["enum"].as_array() {
+        if !options.contains(value) {
+            return Err(format!("{} should be one of {}", path, schema["enum"]));
+        }
+    }
+
+    match value {
+        Value::String(text) => {
+            let min_length = schema["minLength"].as_u64().unwrap_or(0) as usize;
+            if text.trim().chars().count() < min_length {
+                return Err(format!("{} should have at least {} characters", path, min_length));
+            }
+        }
+        Value::Array(items) => {
+            let min_items = schema["minItems"].as_u64().unwrap_or(0) as usize;
+            let max_items = schema["maxItems"].as_u64().map_or(usize::MAX, |max| max as usize);
+            if items.len() < min_items || items.len() > max_items {
+                return Err(format!("{} has {} items, outside the allowed {}..{}", path, items.len(), min_items, schema["maxItems"]));
+            }
+            for (index, item) in items.iter().enumerate() {
+                check(item, &schema["items"], &format!("{}[{}]", path, index))?;
+            }
+        }
+        Value::Object(fields) => check_object(fields, schema, path)?,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn check_object(fields: &Map<String, Value>, schema: &Value, path: &str) -> Result<(), String> {
+    let empty = Map::new();
+    let properties = schema["properties"].as_object().unwrap_or(&empty);
+
+    for name in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+        if !fields.contains_key(name) {
+            return Err(format!("{}.{} is missing", path, name));
+        }
+    }
+
+    for (name, field) in fields {
+        match properties.get(name) {
+            Some(property) => check(field, property, &format!("{}.{}", path, name))?,
+            None if schema["additionalProperties"] == Value::Bool(false) => {
+                return Err(format!("{}.{} is not allowed", path, name));
+            }
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+// EXAMPLE (the smallest value that fits, strings filled with the given text), for answering without a model
+pub fn example(schema: &Value, text: &str) -> Value {
+    if let Some(first) = schema["enum"].as_array().and_then(|options| options.first()) {
+        return first.clone();
+    }
+
+    let kind = match &schema["type"] {
+        Value::Array(types) => types.iter().filter_map(Value::as_str).find(|kind| *kind != "null").unwrap_or("null"),
+        other => other.as_str().unwrap_or("null"),
+    };
+
+    match kind {
+        "string" => Value::String(text.to_string()),
+        "integer" | "number" => Value::from(0),
+        "boolean" => Value::Bool(false),
+        "array" => {
+            let count = schema["minItems"].as_u64().unwrap_or(1).max(1);
+            Value::Array((0..count).map(|_| example(&schema["items"], text)).collect())
+        }
+        "object" => Value::Object(
+            schema["properties"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(name, property)| (name.clone(), example(property, text)))
+                .collect(),
+        ),
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "title": { "type": "string", "minLength": 1 },
+                "score": { "type": "number" },
+                "items": {
+                    "type": "array",
+                    "minItems": 1,
+                    "maxItems": 2,
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "task": { "type": "string" },
+                            "priority": { "enum": ["low", "high"] },
+                            "due": { "type": ["string", "null"] },
+                        },
+                        "required": ["task", "priority"],
+                        "additionalProperties": false,
+                    },
+                },
+            },
+            "required": ["title", "items"],
+            "additionalProperties": false,
+        })
+    }
+
+    #[test]
+    fn valid_values_pass() {
+        let value = json!({
+            "title": "Week",
+            "score": 3,
+            "items": [{ "task": "Read", "priority": "low", "due": null }],
+        });
+        assert_eq!(validate(&value, &schema()), Ok(()));
+    }
+
+    #[test]
+    fn errors_name_the_path() {
+        let value = json!({ "title": "Week", "items": [{ "task": "Read", "priority": "low" }, { "task": "Write", "priority": "urgent" }] });
+        assert!(validate(&value, &schema()).unwrap_err().starts_with("$.items[1].priority should be one of"));
+
+        let value = json!({ "title": "Week", "items": [{ "task": 1, "priority": "low" }] });
+        assert_eq!(validate(&value, &schema()).unwrap_err(), "$.items[0].task should be \"string\", not integer");
+    }
+
+    #[test]
+    fn missing_and_extra_fields_fail() {
+        let value = json!({ "items": [{ "task": "Read", "priority": "low" }] });
+        assert_eq!(validate(&value, &schema()).unwrap_err(), "$.title is missing");
+
+        let value = json!({ "title": "Week", "items": [{ "task": "Read", "priority": "low" }], "extra": true });
+        assert_eq!(validate(&value, &schema()).unwrap_err(), "$.extra is not allowed");
+    }
+
+    #[test]
+    fn lengths_are_checked() {
+        let value = json!({ "title": "  ", "items": [{ "task": "Read", "priority": "low" }] });
+        assert_eq!(validate(&value, &schema()).unwrap_err(), "$.title should have at least 1 characters");
+
+        let value = json!({ "title": "Week", "items": [] });
+        assert!(validate(&value, &schema()).unwrap_err().starts_with("$.items has 0 items"));
+    }
+
+    #[test]
+    fn examples_fit_their_schema() {
+        let value = example(&schema(), "text");
+        assert_eq!(validate(&value, &schema()), Ok(()));
+        assert_eq!(value["items"][0]["priority"], "low");
+    }
+}
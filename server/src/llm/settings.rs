@@ -0,0 +1,93 @@
+use serde::Serialize;
+use std::time::Instant;
+use super::error::LlmError;
+use super::provider::{ChatMessage, ChatRequest, LlmProvider};
+
+// settings a user can bring themselves, anything else is rejected
+pub const AI_SETTINGS: [&str; 4] = ["api_key", "base_url", "temperature", "max_tokens"];
+
+// Provider settings read at runtime, a user's own ones win over the server's environment
+// api_key and base_url only apply to OpenAI-compatible servers
+#[derive(Clone, Debug, Default)]
+pub struct LlmSettings {
+    pub api_key: Option<String>,
+    pub base_url: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+// What the frontend sees of a user's settings, the key only by its last characters
+#[derive(Debug, Serialize)]
+pub struct SettingsSummary {
+    pub api_key: Option<String>,
+    pub base_url: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+// Outcome of a test request with the chosen settings
+#[derive(Debug, Serialize)]
+pub struct SettingsTest {
+    pub model: String,
+    pub reply: String,
+    pub elapsed_ms: u64,
+}
+
+// Implementation for llm settings methods
+impl LlmSettings {
+    // sets one (already validated) setting, None clears it
+    pub fn set(&mut self, name: &str, value: Option<String>) {
+        match name {
+            "api_key" => self.api_key = value,
+            "base_url" => self.base_url = value,
+            "temperature" => self.temperature = value.and_then(|value| value.parse().ok()),
+            "max_tokens" => self.max_tokens = value.and_then(|value| value.parse().ok()),
+            _ => {}
+        }
+    }
+
+    // LLM_TEMPERATURE when the user hasn't chosen one
+    pub fn temperature(&self) -> Option<f32> {
+        self.temperature.or_else(|| std::env::var("LLM_TEMPERATURE").ok()?.parse().ok())
+    }
+
+    // LLM_MAX_TOKENS when the user hasn't chosen one
+    pub fn max_tokens(&self) -> Option<u32> {
+        self.max_tokens.or_else(|| std::env::var("LLM_MAX_TOKENS").ok()?.parse().ok())
+    }
+
+    pub fn summary(&self) -> SettingsSummary {
+        SettingsSummary {
+            api_key: self.api_key.as_deref().map(mask_key),
+            base_url: self.base_url.clone(),
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+        }
+    }
+}
+
+// "…" and the last four characters, enough to tell keys apart
+fn mask_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    let tail: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+    format!("…{}", tail)
+}
+
+// TEST SETTINGS (a short request, so a wrong key or server shows up straight away)
+pub async fn test_provider(provider: &dyn LlmProvider, model: Option<String>) -> Result<SettingsTest, LlmError> {
+    let model = model.unwrap_or_else(|| provider.default_model().to_string());
+    let request = ChatRequest {
+        model: Some(model.clone()),
+        messages: vec![ChatMessage::user("Reply with the single word OK.")],
+        schema: None,
+    };
+
+    let started = Instant::now();
+    let reply = provider.complete(&request).await?;
+
+    Ok(SettingsTest {
+        model,
+        reply,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+use tauri::{AppHandle, Emitter};
+use tokio::task::AbortHandle;
+use super::error::LlmError;
+use crate::db::error::DbError;
+use super::provider::{ChatMessage, ChatRequest, LlmProvider, Usage};
+
+// event carrying each piece of a streamed answer
+pub const TOKEN_EVENT: &str = "neuro-token";
+// event sent once a streamed answer ends (finished, cancelled or failed)
+pub const DONE_EVENT: &str = "neuro-done";
+
+// Token event payload, tagged so concurrent requests can be told apart
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NeuroToken {
+    pub request_id: String,
+    pub token: String,
+}
+
+// Done event payload with usage stats
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NeuroDone {
+    pub request_id: String,
+    pub usage: Usage,
+    pub elapsed_ms: u64,
+    pub cancelled: bool,
+    pub error: Option<LlmError>,
+}
+
+// In-flight streamed requests by request id, held in tauri state so they can be cancelled
+#[derive(Default)]
+pub struct NeuroStreams(Mutex<HashMap<String, AbortHandle>>);
+
+// Implementation for neuro streams methods
+impl NeuroStreams {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, AbortHandle>>, String> {
+        self.0.lock().map_err(|e| e.to_string())
+    }
+
+    // aborts a request, false if it already finished
+    pub fn cancel(&self, request_id: &str) -> Result<bool, String> {
+        match self.lock()?.remove(request_id) {
+            Some(handle) => {
+                handle.abort();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+// STREAM NEURO
+// tokens are emitted as they arrive, the request runs as its own task so cancel_neuro can abort it
+pub async fn stream_neuro(app: AppHandle, streams: &NeuroStreams, request_id: String, prompt: String, provider: Box<dyn LlmProvider>, model: Option<String>) -> Result<NeuroDone, DbError> {
+    let started = Instant::now();
+
+    let task = {
+        let mut in_flight = streams.lock()?;
+        if in_flight.contains_key(&request_id) {
+            return Err(DbError::Invalid(format!("Request {} is already running", request_id)));
+        }
+
+        let app = app.clone();
+        let id = request_id.clone();
+        let task = tokio::spawn(async move {
+            let request = ChatRequest { model, messages: vec![ChatMessage::user(prompt)], schema: None };
+            let mut on_token = |token: &str| {
+                // the frontend may have stopped listening, nothing to do then
+                let _ = app.emit(TOKEN_EVENT, NeuroToken { request_id: id.clone(), token: token.to_string() });
+            };
+
+            provider.stream(&request, &mut on_token).await
+        });
+
+        in_flight.insert(request_id.clone(), task.abort_handle());
+        task
+    };
+
+    let result = task.await;
+    streams.lock()?.remove(&request_id);
+
+    let mut done = NeuroDone {
+        request_id,
+        usage: Usage::default(),
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        cancelled: false,
+        error: None,
+    };
+
+    let failure = match result {
+        Ok(Ok(usage)) => {
+            done.usage = usage;
+            None
+        }
+        Ok(Err(e)) => {
+            done.error = Some(e.clone());
+            Some(DbError::Ai(e))
+        }
+        Err(e) if e.is_cancelled() => {
+            done.cancelled = true;
+            None
+        }
+        // the task panicked, the done event still tells the frontend to stop waiting
+        Err(e) => Some(DbError::Other(e.to_string())),
+    };
+
+    app.emit(DONE_EVENT, &done).map_err(|e| e.to_string())?;
+
+    match failure {
+        Some(error) => Err(error),
+        None => Ok(done),
+    }
+}
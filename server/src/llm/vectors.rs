@@ -0,0 +1,116 @@
+use std::collections::HashSet;
+use super::chunks::Chunk;
+use super::error::LlmError;
+use super::provider::LlmProvider;
+
+// chunks sent to the embeddings endpoint per request
+const EMBED_BATCH: usize = 64;
+
+// Embedded chunk, what the index keeps per chunk
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct EmbeddedChunk {
+    pub heading: String,
+    pub text: String,
+    pub vector: Vec<f32>,
+}
+
+// Semantic search result
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SearchHit {
+    pub note_id: String, // synced note id, or the file path for local notes
+    pub note: String,
+    pub heading: String,
+    pub text: String,
+    pub score: f32, // cosine similarity, higher is closer
+}
+
+// what identifies a chunk's embedding, hashed by the caller to get its index key
+// the model is part of it, so switching models re-embeds everything
+pub fn chunk_fingerprint(model: &str, chunk: &Chunk) -> String {
+    format!("{}\u{0}{}\u{0}{}", model, chunk.heading, chunk.text)
+}
+
+// cosine similarity, 0 for vectors of different sizes or no length
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|y| y * y).sum::<f32>().sqrt();
+
+    if norm == 0.0 { 0.0 } else { dot / norm }
+}
+
+// Chunks that need embedding and index keys that no longer match any chunk
+pub struct IndexUpdate {
+    pub embed: Vec<(String, Chunk)>,
+    pub stale: Vec<String>,
+}
+
+// PLAN UPDATE (only new or changed chunks are embedded again)
+pub fn plan_update(existing: &HashSet<String>, chunks: Vec<(String, Chunk)>) -> IndexUpdate {
+    let current: HashSet<&String> = chunks.iter().map(|(key, _)| key).collect();
+    let stale = existing.iter().filter(|key| !current.contains(key)).cloned().collect();
+
+    let mut seen: HashSet<String> = HashSet::new();
+    let embed = chunks
+        .into_iter()
+        .filter(|(key, _)| !existing.contains(key) && seen.insert(key.clone()))
+        .collect();
+
+    IndexUpdate { embed, stale }
+}
+
+// EMBED CHUNKS (in batches, the heading is embedded with the text)
+pub async fn embed_chunks(provider: &dyn LlmProvider, chunks: Vec<(String, Chunk)>) -> Result<Vec<(String, EmbeddedChunk)>, LlmError> {
+    let mut embedded: Vec<(String, EmbeddedChunk)> = Vec::new();
+
+    for batch in chunks.chunks(EMBED_BATCH) {
+        let texts: Vec<String> = batch
+            .iter()
+            .map(|(_, chunk)| format!("{}\n{}", chunk.heading, chunk.text))
+            .collect();
+
+        let vectors = provider.embed(&texts).await?;
+        if vectors.len() != batch.len() {
+            return Err(LlmError::Malformed("wrong number of embeddings".to_string()));
+        }
+
+        for ((key, chunk), vector) in batch.iter().zip(vectors) {
+            embedded.push((key.clone(), EmbeddedChunk { heading: chunk.heading.clone(), text: chunk.text.clone(), vector }));
+        }
+    }
+
+    Ok(embedded)
+}
+
+// Brute-force cosine index, fine for the few thousand chunks a vault has
+pub struct VectorIndex<T> {
+    entries: Vec<(T, Vec<f32>)>,
+}
+
+impl<T> Default for VectorIndex<T> {
+    fn default() -> Self {
+        Self { entries: Vec::new() }
+    }
+}
+
+// Implementation for vector index methods
+impl<T> VectorIndex<T> {
+    pub fn insert(&mut self, item: T, vector: Vec<f32>) {
+        self.entries.push((item, vector));
+    }
+
+    // the k entries closest to the query, closest first
+    pub fn search(self, query: &[f32], k: usize) -> Vec<(f32, T)> {
+        let mut scored: Vec<(f32, T)> = self.entries
+            .into_iter()
+            .map(|(item, vector)| (cosine(query, &vector), item))
+            .collect();
+
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(k);
+        scored
+    }
+}
@@ -0,0 +1,114 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::json;
+use std::time::Duration;
+
+// longest wait on the mail relay
+const RELAY_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Delivers account messages (password reset links) to a user
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send_password_reset(&self, email: &str, token: &str) -> Result<(), String>;
+}
+
+// Posts messages to a mail relay (RESET_NOTIFIER_URL), which delivers them to the user's own inbox
+// RESET_NOTIFIER_SECRET, when set, is sent as a bearer token so the relay only accepts this server
+pub struct RelayNotifier {
+    url: String,
+    secret: Option<String>,
+    client: Client,
+}
+
+// Implementation for relay notifier methods
+impl RelayNotifier {
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("RESET_NOTIFIER_URL").ok().filter(|url| !url.trim().is_empty())?;
+
+        Some(Self {
+            url,
+            secret: std::env::var("RESET_NOTIFIER_SECRET").ok(),
+            client: Client::builder().timeout(RELAY_TIMEOUT).build().unwrap_or_default(),
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for RelayNotifier {
+    async fn send_password_reset(&self, email: &str, token: &str) -> Result<(), String> {
+        let mut request = self.client
+            .post(&self.url)
+            .json(&json!({ "kind": "password_reset", "email": email, "token": token }));
+        if let Some(secret) = &self.secret {
+            request = request.bearer_auth(secret);
+        }
+
+        request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|e| format!("Could not send the reset email: {}", e))
+    }
+}
+
+// Writes messages to a file, or the log when no file is configured (local testing only)
+// the token ends up on the machine that asked for the reset, so release builds never include it
+#[cfg(any(debug_assertions, test))]
+pub struct FileNotifier {
+    path: Option<std::path::PathBuf>,
+}
+
+// Implementation for file notifier methods
+#[cfg(any(debug_assertions, test))]
+impl FileNotifier {
+    // reads the output file from RESET_NOTIFIER_FILE
+    pub fn from_env() -> Self {
+        Self {
+            path: std::env::var("RESET_NOTIFIER_FILE").ok().map(std::path::PathBuf::from),
+        }
+    }
+}
+
+#[cfg(any(debug_assertions, test))]
+#[async_trait]
+impl Notifier for FileNotifier {
+    async fn send_password_reset(&self, email: &str, token: &str) -> Result<(), String> {
+        use std::io::Write;
+
+        let message = format!("password reset for {}: {}", email, token);
+
+        let Some(path) = &self.path else {
+            log::info!("{}", message);
+            return Ok(());
+        };
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| e.to_string())?;
+
+        writeln!(file, "{}", message).map_err(|e| e.to_string())
+    }
+}
+
+// notifier held in tauri state
+pub struct Notifications(pub Box<dyn Notifier>);
+
+// Implementation for notifications methods
+impl Notifications {
+    // the mail relay, falling back to the file notifier in debug builds
+    // a release build without a relay fails to start rather than hand out reset tokens locally
+    pub fn from_env() -> Result<Self, String> {
+        if let Some(relay) = RelayNotifier::from_env() {
+            return Ok(Self(Box::new(relay)));
+        }
+
+        #[cfg(any(debug_assertions, test))]
+        return Ok(Self(Box::new(FileNotifier::from_env())));
+
+        #[cfg(not(any(debug_assertions, test)))]
+        Err("RESET_NOTIFIER_URL must be set, password reset emails have nowhere to go".to_string())
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::db::crypto::hash_token;
+use crate::llm::chunks::{chunk_markdown, Chunk};
+use crate::llm::provider::LlmProvider;
+use crate::llm::vectors::{chunk_fingerprint, embed_chunks, plan_update, EmbeddedChunk, SearchHit, VectorIndex};
+
+// hidden folder (inside the notebook) local embeddings are stored in, one file per note
+const INDEX_DIR: &str = ".neuro/embeddings";
+
+fn is_note(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|ext| ext == "md")
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+// index file of a local note
+fn index_file(note: &Path) -> Result<PathBuf, String> {
+    let notebook = note.parent().ok_or("Invalid note path")?;
+    let name = note.file_name().ok_or("Invalid note path")?.to_string_lossy();
+
+    Ok(notebook.join(INDEX_DIR).join(format!("{}.json", name)))
+}
+
+// stored chunks by key, empty when the note hasn't been indexed (or the file is unreadable)
+fn load_index(file: &Path) -> HashMap<String, EmbeddedChunk> {
+    fs::read_to_string(file)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+// notes in a local note, notebook or vault path (hidden folders are skipped)
+fn scope_notes(scope: &Path) -> Result<Vec<PathBuf>, String> {
+    if is_note(scope) {
+        return Ok(vec![scope.to_path_buf()]);
+    }
+
+    let mut notes: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(scope).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+
+        if is_note(&path) {
+            notes.push(path);
+        } else if path.is_dir() && !is_hidden(&path) {
+            notes.extend(scope_notes(&path)?);
+        }
+    }
+    notes.sort();
+
+    Ok(notes)
+}
+
+// INDEX LOCAL NOTE (only chunks that changed since the last time are embedded), returns how many were
+pub async fn index_local_note(note: &Path, provider: &dyn LlmProvider) -> Result<usize, String> {
+    let file = index_file(note)?;
+    let mut index = load_index(&file);
+    let model = provider.embedding_model();
+
+    let content = fs::read_to_string(note).map_err(|e| e.to_string())?;
+    let chunks: Vec<(String, Chunk)> = chunk_markdown(&content)
+        .into_iter()
+        .map(|chunk| (hash_token(&chunk_fingerprint(&model, &chunk)), chunk))
+        .collect();
+
+    let existing: HashSet<String> = index.keys().cloned().collect();
+    let update = plan_update(&existing, chunks);
+    if update.embed.is_empty() && update.stale.is_empty() {
+        return Ok(0);
+    }
+
+    let embedded = embed_chunks(provider, update.embed).await?;
+    let count = embedded.len();
+
+    for key in update.stale {
+        index.remove(&key);
+    }
+    index.extend(embedded);
+
+    fs::create_dir_all(file.parent().ok_or("Invalid note path")?).map_err(|e| e.to_string())?;
+    fs::write(&file, serde_json::to_string(&index).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+
+    Ok(count)
+}
+
+// SEMANTIC SEARCH (local note, notebook or vault path, closest chunks first)
+pub async fn search_local_notes(scope: &Path, query: String, top_k: usize, provider: &dyn LlmProvider) -> Result<Vec<SearchHit>, String> {
+    let mut index: VectorIndex<(String, String, String, String)> = VectorIndex::default();
+
+    for note in scope_notes(scope)? {
+        index_local_note(&note, provider).await?;
+
+        let path = note.to_string_lossy().into_owned();
+        let name = note.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        for chunk in load_index(&index_file(&note)?).into_values() {
+            index.insert((path.clone(), name.clone(), chunk.heading, chunk.text), chunk.vector);
+        }
+    }
+
+    let query = provider.embed(&[query]).await?;
+    let query = query.first().ok_or("The provider returned no embedding")?;
+
+    Ok(index
+        .search(query, top_k)
+        .into_iter()
+        .map(|(score, (note_id, note, heading, text))| SearchHit { note_id, note, heading, text, score })
+        .collect())
+}
@@ -0,0 +1,106 @@
+use std::sync::{Mutex, MutexGuard};
+use crate::db::access::authorize;
+use crate::db::client::now;
+use crate::db::sessions::rotate_session;
+use crate::db::crypto::UserKeys;
+use crate::db::error::{DbError, DbResult};
+
+// Session of the logged in user
+#[derive(Clone)]
+pub struct ActiveSession {
+    pub token: String,
+    pub keys: UserKeys,
+}
+
+// Login waiting on a two-factor code (the password was already accepted)
+#[derive(Clone)]
+pub struct PendingLogin {
+    pub email: String,
+    pub device: Option<String>,
+    pub keys: UserKeys,
+    expires_at: i64,
+}
+
+// held in tauri state, so the frontend never decides who the user is
+#[derive(Default)]
+pub struct Session {
+    active: Mutex<Option<ActiveSession>>,
+    pending: Mutex<Option<PendingLogin>>,
+}
+
+// seconds a pending login waits for its two-factor code
+const PENDING_SECONDS: i64 = 300;
+
+// Implementation for session methods
+impl Session {
+    fn lock(&self) -> DbResult<MutexGuard<'_, Option<ActiveSession>>> {
+        self.active.lock().map_err(|e| DbError::Other(e.to_string()))
+    }
+
+    fn lock_pending(&self) -> DbResult<MutexGuard<'_, Option<PendingLogin>>> {
+        self.pending.lock().map_err(|e| DbError::Other(e.to_string()))
+    }
+
+    // stores the session after login
+    pub fn start(&self, token: String, keys: UserKeys) -> DbResult<()> {
+        *self.lock_pending()? = None;
+        *self.lock()? = Some(ActiveSession { token, keys });
+        Ok(())
+    }
+
+    // holds a login until its two-factor code arrives
+    pub fn await_two_factor(&self, email: String, device: Option<String>, keys: UserKeys) -> DbResult<()> {
+        *self.lock_pending()? = Some(PendingLogin { email, device, keys, expires_at: now() + PENDING_SECONDS });
+        Ok(())
+    }
+
+    // the login waiting on a two-factor code
+    pub fn pending(&self) -> DbResult<PendingLogin> {
+        let mut pending = self.lock_pending()?;
+
+        match pending.clone() {
+            Some(login) if login.expires_at > now() => Ok(login),
+            _ => {
+                *pending = None;
+                Err(DbError::SessionExpired)
+            }
+        }
+    }
+
+    // forgets the session, returning it so it can be deleted from redis
+    pub fn end(&self) -> DbResult<Option<ActiveSession>> {
+        Ok(self.lock()?.take())
+    }
+
+    // the current session, checked against redis so expired or deleted sessions are refused
+    pub async fn current(&self) -> DbResult<ActiveSession> {
+        let session = self.lock()?.clone().ok_or(DbError::Unauthorized)?;
+
+        if let Err(e) = authorize(&session.token, &session.keys).await {
+            // expired sessions take the keys with them
+            if matches!(e, DbError::SessionExpired) {
+                let mut state = self.lock()?;
+                if state.as_ref().is_some_and(|active| active.token == session.token) {
+                    *state = None;
+                }
+            }
+            return Err(e);
+        }
+
+        Ok(session)
+    }
+
+    // swaps the token for a new one, the absolute expiry stays the same
+    pub async fn refresh(&self) -> DbResult<String> {
+        let session = self.current().await?;
+        let token = rotate_session(session.token).await?;
+
+        self.start(token.clone(), session.keys)?;
+        Ok(token)
+    }
+
+    // unlocked keys of the current user
+    pub async fn keys(&self) -> DbResult<UserKeys> {
+        Ok(self.current().await?.keys)
+    }
+}
@@ -0,0 +1,108 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::command;
+use uuid::Uuid;
+use crate::llm::conversation::{context_budget, truncate_turns, Thread, ThreadSummary};
+
+// hidden folder (inside the notebook) local threads are stored in, skipped by exports
+const THREAD_DIR: &str = ".neuro/threads";
+
+// thread folder and scope tag for a local note (its file name) or notebook (empty)
+fn thread_folder(scope: &Path) -> Result<(PathBuf, String), String> {
+    if scope.is_dir() {
+        return Ok((scope.join(THREAD_DIR), String::new()));
+    }
+
+    let notebook = scope.parent().ok_or("Invalid note path")?;
+    let name = scope.file_name().ok_or("Invalid note path")?.to_string_lossy().into_owned();
+
+    Ok((notebook.join(THREAD_DIR), name))
+}
+
+// thread file, ids are checked so they can't point outside the folder
+fn thread_file(folder: &Path, id: &str) -> Result<PathBuf, String> {
+    Uuid::parse_str(id).map_err(|_| "Invalid thread id")?;
+    Ok(folder.join(format!("{}.json", id)))
+}
+
+// LOAD THREAD (must belong to the scope)
+pub fn load_thread(scope: &str, id: &str) -> Result<Thread, String> {
+    let (folder, tag) = thread_folder(Path::new(scope))?;
+    let json = fs::read_to_string(thread_file(&folder, id)?).map_err(|e| e.to_string())?;
+    let thread: Thread = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+
+    if thread.scope != tag {
+        return Err("Thread not found".to_string());
+    }
+    Ok(thread)
+}
+
+// new local thread attached to the scope
+pub fn new_thread(scope: &str, prompt: &str, now: i64) -> Result<Thread, String> {
+    let (_, tag) = thread_folder(Path::new(scope))?;
+    Ok(Thread::new(Uuid::new_v4().to_string(), tag, prompt, now))
+}
+
+// SAVE THREAD
+pub fn save_thread(scope: &str, thread: &Thread) -> Result<(), String> {
+    let (folder, _) = thread_folder(Path::new(scope))?;
+    let json = serde_json::to_string_pretty(thread).map_err(|e| e.to_string())?;
+
+    fs::create_dir_all(&folder).map_err(|e| e.to_string())?;
+    fs::write(thread_file(&folder, &thread.id)?, json).map_err(|e| e.to_string())
+}
+
+// lists the threads of a local note or notebook, most recent first
+#[command]
+pub fn list_threads(scope: &str) -> Result<Vec<ThreadSummary>, String> {
+    let (folder, tag) = thread_folder(Path::new(scope))?;
+
+    let entries = match fs::read_dir(&folder) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()), // no threads yet
+    };
+
+    let mut threads: Vec<ThreadSummary> = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let thread: Option<Thread> = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok());
+
+        // unreadable files are skipped rather than hiding every other thread
+        if let Some(thread) = thread.filter(|thread| thread.scope == tag) {
+            threads.push(thread.summary());
+        }
+    }
+    threads.sort_by_key(|thread| Reverse(thread.updated_at));
+
+    Ok(threads)
+}
+
+// reads a local thread with all its turns
+#[command]
+pub fn read_thread(scope: &str, id: &str) -> Result<Thread, String> {
+    load_thread(scope, id)
+}
+
+// drops the oldest turns of a local thread beyond the budget (LLM_CONTEXT_TOKENS by default), returns how many went
+#[command]
+pub fn truncate_thread(scope: &str, id: &str, max_tokens: Option<usize>) -> Result<usize, String> {
+    let mut thread = load_thread(scope, id)?;
+    let removed = truncate_turns(&mut thread, max_tokens.unwrap_or_else(context_budget));
+
+    if removed > 0 {
+        save_thread(scope, &thread)?;
+    }
+    Ok(removed)
+}
+
+// deletes a local thread
+#[command]
+pub fn delete_thread(scope: &str, id: &str) -> Result<(), String> {
+    let (folder, _) = thread_folder(Path::new(scope))?;
+    load_thread(scope, id)?;
+
+    fs::remove_file(thread_file(&folder, id)?).map_err(|e| e.to_string())
+}
@@ -0,0 +1,27 @@
+import { goto } from '$app/navigation';
+import { Window, LogicalSize } from '@tauri-apps/api/window';
+import toast from 'svelte-5-french-toast';
+
+// error shape returned by synced commands
+export interface CommandError {
+    kind: string;
+    message: string;
+}
+
+// Sends the user back to login when their session has expired or is invalid,
+// returns false for any other error so the caller can handle it
+export async function handleSessionError(error: unknown): Promise<boolean> {
+    const kind = (error as CommandError)?.kind;
+    if (kind !== 'session_expired' && kind !== 'unauthorized') {
+        return false;
+    }
+
+    localStorage.removeItem('session_token');
+    toast.error((error as CommandError).message);
+
+    const win = Window.getCurrent();
+    await win.setSize(new LogicalSize(800, 650));
+    await win.center();
+    goto('/user-auth/login');
+    return true;
+}